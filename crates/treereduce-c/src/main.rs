@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use treereduce::InlineQueries;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_c::LANGUAGE.into(),
         tree_sitter_c::NODE_TYPES,
+        &["c", "h"],
         // ("parameter_declaration", &["int x"][..]),
         HashMap::from([
             ("compound_statement", &["{}"][..]),
@@ -19,5 +21,6 @@ fn main() -> Result<()> {
             // - function_definition is optional wherever it appears, no need
             //   to replace
         ]),
+        Some(InlineQueries::C),
     )
 }
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use treereduce::InlineQueries;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_java::LANGUAGE.into(),
         tree_sitter_java::NODE_TYPES,
+        &["java"],
         HashMap::new(),
+        Some(InlineQueries::JAVA),
     )
 }
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use treereduce::InlineQueries;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_javascript::LANGUAGE.into(),
         tree_sitter_javascript::NODE_TYPES,
+        &["js", "mjs", "cjs"],
         HashMap::new(),
+        Some(InlineQueries::JAVASCRIPT),
     )
 }
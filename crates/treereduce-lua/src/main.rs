@@ -6,6 +6,8 @@ fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_lua::LANGUAGE.into(),
         tree_sitter_lua::NODE_TYPES,
+        &["lua"],
         HashMap::new(),
+        None,
     )
 }
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use treereduce::InlineQueries;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_rust::LANGUAGE.into(),
         tree_sitter_rust::NODE_TYPES,
+        &["rs"],
         HashMap::from([("type_identifier", &["()"][..])]),
+        Some(InlineQueries::RUST),
     )
 }
@@ -6,6 +6,7 @@ fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_souffle::LANGUAGE.into(),
         tree_sitter_souffle::NODE_TYPES,
+        &["dl"],
         HashMap::from([
             // ("atom", &["0 = 0"][..]),
             // ("disjunction", &[""][..]),
@@ -14,5 +15,6 @@ fn main() -> Result<()> {
             ("string", &["\"\""][..]),
             ("number", &["0"][..]),
         ]),
+        None,
     )
 }
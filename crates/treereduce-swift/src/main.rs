@@ -6,6 +6,8 @@ fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_swift::LANGUAGE.into(),
         tree_sitter_swift::NODE_TYPES,
+        &["swift"],
         HashMap::new(),
+        None,
     )
 }
@@ -1,16 +1,24 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::io::{self, Read};
+use std::ops::Range;
 #[cfg(target_family = "unix")]
 use std::os::unix::process::ExitStatusExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use regex::Regex;
-use tempfile::NamedTempFile;
+use tempfile::{NamedTempFile, TempDir};
 use tracing::debug;
 use wait_timeout::ChildExt;
 
+use crate::project;
+
 pub trait Check {
     type State;
 
@@ -25,6 +33,163 @@ pub trait Check {
     fn interesting(&self, stdin: &[u8]) -> io::Result<bool> {
         self.wait(self.start(stdin)?)
     }
+
+    /// Like [`Check::start`], for test cases made of several files
+    fn start_files(&self, files: &[&[u8]]) -> io::Result<Self::State> {
+        match files {
+            [file] => self.start(file),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "check doesn't support multiple files",
+            )),
+        }
+    }
+
+    fn interesting_files(&self, files: &[&[u8]]) -> io::Result<bool> {
+        self.wait(self.start_files(files)?)
+    }
+}
+
+/// Checks one file of several, passing the others along unchanged
+#[derive(Debug)]
+pub(crate) struct InContext<'a, T> {
+    check: &'a T,
+    files: Vec<&'a [u8]>,
+    index: usize,
+}
+
+impl<'a, T> InContext<'a, T> {
+    pub(crate) fn new(check: &'a T, files: Vec<&'a [u8]>, index: usize) -> Self {
+        debug_assert!(index < files.len());
+        InContext {
+            check,
+            files,
+            index,
+        }
+    }
+}
+
+impl<T: Check> Check for InContext<'_, T> {
+    type State = T::State;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        let mut files = self.files.clone();
+        files[self.index] = stdin;
+        self.check.start_files(&files)
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        self.check.cancel(state)
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        self.check.try_wait(state)
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        self.check.wait(state)
+    }
+}
+
+/// Remembers the verdict for each test case, so that it's only checked once.
+/// Clones share their verdicts. Only useful if the check is deterministic.
+#[derive(Clone, Debug)]
+pub struct Cached<T> {
+    check: T,
+    /// Verdicts, by hash of the test case
+    verdicts: Arc<Mutex<HashMap<u64, bool>>>,
+}
+
+impl<T> Cached<T> {
+    pub fn new(check: T) -> Self {
+        Cached {
+            check,
+            verdicts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of test cases that have been checked
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.verdicts.lock().map_or(0, |v| v.len())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lookup(&self, key: u64) -> Option<bool> {
+        self.verdicts.lock().ok()?.get(&key).copied()
+    }
+
+    fn remember(&self, key: u64, verdict: bool) {
+        if let Ok(mut verdicts) = self.verdicts.lock() {
+            verdicts.insert(key, verdict);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CachedState<S> {
+    Known(bool),
+    Running { state: S, key: u64 },
+}
+
+fn hash_of(files: &[&[u8]]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    files.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: Check> Check for Cached<T> {
+    type State = CachedState<T::State>;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        self.start_files(&[stdin])
+    }
+
+    fn start_files(&self, files: &[&[u8]]) -> io::Result<Self::State> {
+        let key = hash_of(files);
+        if let Some(verdict) = self.lookup(key) {
+            return Ok(CachedState::Known(verdict));
+        }
+        Ok(CachedState::Running {
+            state: self.check.start_files(files)?,
+            key,
+        })
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        match state {
+            CachedState::Known(_) => Ok(()),
+            CachedState::Running { state, .. } => self.check.cancel(state),
+        }
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        match state {
+            CachedState::Known(verdict) => Ok(Some(*verdict)),
+            CachedState::Running { state, key } => {
+                let verdict = self.check.try_wait(state)?;
+                if let Some(v) = verdict {
+                    self.remember(*key, v);
+                }
+                Ok(verdict)
+            }
+        }
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        match state {
+            CachedState::Known(verdict) => Ok(verdict),
+            CachedState::Running { state, key } => {
+                let verdict = self.check.wait(state)?;
+                self.remember(key, verdict);
+                Ok(verdict)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -38,6 +203,12 @@ pub struct CmdCheck {
     uninteresting_stdout: Option<Regex>,
     temp_dir: PathBuf,
     pub(crate) needs_file: bool,
+    /// Names of the files in multi-file test cases
+    file_names: Vec<String>,
+    /// Directory containing the files, which is copied for each check
+    project: Option<PathBuf>,
+    /// Arguments that may be deleted, see [`crate::reduce_flags`]
+    reducible: Option<Range<usize>>,
     inherit_stdout: bool,
     inherit_stderr: bool,
     timeout: Option<Duration>,
@@ -48,13 +219,23 @@ pub struct CmdCheck {
 #[derive(Debug)]
 pub struct CmdCheckState {
     child: Child,
-    temp_file: Option<NamedTempFile>,
+    temp_files: Vec<NamedTempFile>,
+    temp_dir: Option<TempDir>,
 }
 
 fn is_marker(s: &str) -> bool {
     s.starts_with("@@")
 }
 
+/// The (0-based) file that an `@@N` marker refers to
+fn marker_index(s: &str) -> Option<usize> {
+    let n = s.strip_prefix("@@")?;
+    if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    n.parse::<usize>().ok()?.checked_sub(1)
+}
+
 impl CmdCheck {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -73,6 +254,9 @@ impl CmdCheck {
         let temp_dir_path: Option<PathBuf> = temp_dir.as_ref().map(From::from);
         CmdCheck {
             needs_file: args.iter().any(|s| is_marker(s)),
+            file_names: Vec::new(),
+            project: None,
+            reducible: None,
             temp_dir: temp_dir_path.unwrap_or_else(std::env::temp_dir),
             cmd,
             args,
@@ -87,6 +271,81 @@ impl CmdCheck {
         }
     }
 
+    /// Set the names of the files in multi-file test cases. They're used for
+    /// the files in the directory that `@@` refers to, and for the extensions
+    /// of the files that `@@1`, `@@2`, etc. refer to.
+    #[must_use]
+    pub fn with_file_names(mut self, file_names: Vec<String>) -> Self {
+        self.file_names = file_names;
+        self
+    }
+
+    /// Run the check in a copy of the project in `dir`, where the files (see
+    /// [`CmdCheck::with_file_names`], which are relative to `dir`) are
+    /// replaced with the ones being checked, or deleted if they're empty.
+    /// `@@` refers to the copy.
+    #[must_use]
+    pub fn with_project(mut self, dir: PathBuf) -> Self {
+        self.project = Some(dir);
+        self
+    }
+
+    /// Allow [`crate::reduce_flags`] to delete these arguments.
+    #[must_use]
+    pub fn with_reducible_args(mut self, reducible: Range<usize>) -> Self {
+        debug_assert!(reducible.end <= self.args.len());
+        self.reducible = Some(reducible);
+        self
+    }
+
+    /// The arguments that may be deleted
+    #[must_use]
+    pub fn reducible_args(&self) -> &[String] {
+        self.reducible
+            .as_ref()
+            .map_or(&[], |r| &self.args[r.clone()])
+    }
+
+    /// The same check, with only the given reducible arguments
+    pub(crate) fn keeping_args(&self, keep: &[bool]) -> Self {
+        let Some(reducible) = self.reducible.clone() else {
+            return self.clone();
+        };
+        debug_assert!(keep.len() == reducible.len());
+        let kept: Vec<String> = self.args[reducible.clone()]
+            .iter()
+            .zip(keep)
+            .filter(|(_, k)| **k)
+            .map(|(a, _)| a.clone())
+            .collect();
+        let mut args = self.args[..reducible.start].to_vec();
+        let start = args.len();
+        args.extend(kept);
+        let end = args.len();
+        args.extend_from_slice(&self.args[reducible.end..]);
+        CmdCheck {
+            needs_file: args.iter().any(|s| is_marker(s)),
+            args,
+            reducible: Some(start..end),
+            ..self.clone()
+        }
+    }
+
+    fn file_name(&self, index: usize) -> String {
+        if self.project.is_some() {
+            if let Some(name) = self.file_names.get(index) {
+                return name.clone();
+            }
+        }
+        self.file_names
+            .get(index)
+            .and_then(|n| Path::new(n).file_name())
+            .map_or_else(
+                || format!("file{}", index + 1),
+                |n| n.to_string_lossy().into_owned(),
+            )
+    }
+
     fn temp_file(&self, marker: &str) -> io::Result<NamedTempFile> {
         debug_assert!(is_marker(marker));
         let mut builder = tempfile::Builder::new();
@@ -128,6 +387,119 @@ impl CmdCheck {
         Ok((temp_file, args))
     }
 
+    /// Replace `@@N` with the path to a temporary file containing the `N`th
+    /// file, and `@@` with the path to a temporary directory containing all of
+    /// them (except the empty ones, which have been deleted).
+    fn args_with_files(
+        &self,
+        files: &[&[u8]],
+    ) -> io::Result<(Vec<NamedTempFile>, Option<TempDir>, Vec<String>)> {
+        let mut temp_files = Vec::new();
+        let mut temp_dir = None;
+        let mut args = Vec::new();
+        for arg in &self.args {
+            if let Some(index) = marker_index(arg) {
+                let file = files.get(index).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("No file for {arg}"))
+                })?;
+                let name = self.file_name(index);
+                let suffix = Path::new(&name)
+                    .extension()
+                    .map(|e| format!(".{}", e.to_string_lossy()))
+                    .unwrap_or_default();
+                let mut f = tempfile::Builder::new()
+                    .prefix("treereduce-tmp-")
+                    .suffix(&suffix)
+                    .tempfile_in(&self.temp_dir)?;
+                f.write_all(file)?;
+                args.push(f.path().to_str().expect("Path not valid UTF-8").to_string());
+                temp_files.push(f);
+            } else if is_marker(arg) {
+                let dir = match &temp_dir {
+                    Some(d) => d,
+                    None => temp_dir.insert(self.scratch_dir(files)?),
+                };
+                args.push(
+                    dir.path()
+                        .to_str()
+                        .expect("Path not valid UTF-8")
+                        .to_string(),
+                );
+            } else {
+                args.push(arg.clone());
+            }
+        }
+        Ok((temp_files, temp_dir, args))
+    }
+
+    /// A temporary directory containing the files (or a copy of the project,
+    /// if there is one)
+    fn scratch_dir(&self, files: &[&[u8]]) -> io::Result<TempDir> {
+        let dir = tempfile::Builder::new()
+            .prefix("treereduce-tmp-")
+            .tempdir_in(&self.temp_dir)?;
+        let names: Vec<String> = (0..files.len()).map(|i| self.file_name(i)).collect();
+        if let Some(project) = &self.project {
+            let files: Vec<(&Path, &[u8])> = names
+                .iter()
+                .map(|n| Path::new(n.as_str()))
+                .zip(files.iter().copied())
+                .collect();
+            project::write(project, dir.path(), &files)?;
+        } else {
+            for (name, file) in names.iter().zip(files) {
+                if !file.is_empty() {
+                    fs::write(dir.path().join(name), file)?;
+                }
+            }
+        }
+        Ok(dir)
+    }
+
+    fn exec_files(&self, files: &[&[u8]]) -> io::Result<CmdCheckState> {
+        if !self.needs_file && self.project.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "multiple files need to be passed to the check with @@ or @@N",
+            ));
+        }
+        let (temp_files, mut temp_dir, args) = self.args_with_files(files)?;
+        if self.project.is_some() && temp_dir.is_none() {
+            temp_dir = Some(self.scratch_dir(files)?);
+        }
+        let child = self.spawn(args, temp_dir.as_ref().map(TempDir::path))?;
+        Ok(CmdCheckState {
+            child,
+            temp_files,
+            temp_dir,
+        })
+    }
+
+    /// Run the command (in `dir`, if it's a project)
+    fn spawn(&self, args: Vec<String>, dir: Option<&Path>) -> io::Result<Child> {
+        let mut cmd = Command::new(&self.cmd);
+        if let (Some(_), Some(dir)) = (&self.project, dir) {
+            cmd.current_dir(dir);
+        }
+        cmd.args(args)
+            .stdin(Stdio::piped())
+            .stdout(if self.inherit_stdout {
+                Stdio::inherit()
+            } else if self.interesting_stdout.is_none() {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            })
+            .stderr(if self.inherit_stderr {
+                Stdio::inherit()
+            } else if self.interesting_stderr.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .spawn()
+    }
+
     fn exec(&self, stdin_bytes: &[u8]) -> io::Result<CmdCheckState> {
         let (mut temp_file, args) = if self.needs_file {
             self.args_with_file()?
@@ -181,7 +553,11 @@ impl CmdCheck {
             }
             child
         };
-        Ok(CmdCheckState { child, temp_file })
+        Ok(CmdCheckState {
+            child,
+            temp_files: temp_file.into_iter().collect(),
+            temp_dir: None,
+        })
     }
 
     fn is_interesting(
@@ -258,6 +634,26 @@ impl CmdCheck {
     }
 }
 
+#[cfg(test)]
+impl CmdCheck {
+    /// Run `cmd` with `args`, interesting if it exits with 0
+    pub(crate) fn exit_zero(cmd: &str, args: &[&str]) -> Self {
+        CmdCheck::new(
+            cmd.to_string(),
+            args.iter().map(ToString::to_string).collect(),
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        )
+    }
+}
+
 impl Check for CmdCheck {
     type State = CmdCheckState;
 
@@ -265,11 +661,21 @@ impl Check for CmdCheck {
         self.exec(stdin)
     }
 
+    fn start_files(&self, files: &[&[u8]]) -> io::Result<Self::State> {
+        match files {
+            [file] if self.project.is_none() => self.exec(file),
+            _ => self.exec_files(files),
+        }
+    }
+
     fn cancel(&self, mut state: Self::State) -> io::Result<()> {
         state.child.kill()?;
-        if let Some(tf) = state.temp_file {
+        for tf in state.temp_files {
             tf.close()?;
         }
+        if let Some(td) = state.temp_dir {
+            td.close()?;
+        }
         Ok(())
     }
 
@@ -296,3 +702,40 @@ impl Check for CmdCheck {
         Ok(self.wait_with_output(state)?.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, marker: &str) -> CmdCheck {
+        CmdCheck::exit_zero("sh", &["-c", script, marker])
+            .with_file_names(vec!["src/main.c".to_string(), "util.h".to_string()])
+    }
+
+    #[test]
+    fn test_start_files() {
+        assert_eq!(marker_index("@@2"), Some(1));
+        assert_eq!(marker_index("@@.c"), None);
+        let files: [&[u8]; 2] = [b"int main;", b""];
+
+        let second = sh(
+            r#"case "$0" in *.h) test ! -s "$0";; *) false;; esac"#,
+            "@@2",
+        );
+        assert!(second.interesting_files(&files).unwrap());
+
+        let dir = sh(r#"grep -q main "$0/main.c" && test ! -e "$0/util.h""#, "@@");
+        assert!(dir.interesting_files(&files).unwrap());
+        assert!(!dir.interesting_files(&[b"int x;", b""]).unwrap());
+    }
+
+    #[test]
+    fn test_cached() {
+        let cached = Cached::new(sh(r#"grep -q x "$0""#, "@@"));
+        let other = cached.clone();
+        assert!(cached.interesting(b"int x;").unwrap());
+        assert!(!other.interesting(b"int y;").unwrap());
+        assert!(cached.interesting(b"int x;").unwrap());
+        assert_eq!(cached.len(), 2);
+    }
+}
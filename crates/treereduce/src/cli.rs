@@ -3,19 +3,24 @@ use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::time::Duration;
 
-use anyhow::{Context, Result};
-use clap::{ArgGroup, Parser};
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use regex::Regex;
+use serde::Serialize;
 use tracing::{error, warn};
 use tracing_subscriber::fmt::format::FmtSpan;
 
-use crate::check::{Check, CmdCheck};
+use crate::check::{Cached, Check, CmdCheck};
+use crate::metric::Metric;
 use crate::original::Original;
 use crate::reduce;
+use crate::rewrite::Rewrite;
 
 mod formatter;
 
@@ -62,8 +67,12 @@ fn handle_parse_errors(path: &str, tree: &tree_sitter::Tree, on_parse_error: &On
 const DEFAULT_MIN_REDUCTION: usize = 2;
 const FAST_MIN_REDUCTION: usize = 4;
 const SLOW_MIN_REDUCTION: usize = 1;
+/// Defaults for `--metric lines` and `--metric nodes`, where one is a lot
+const COARSE_MIN_REDUCTION: usize = 1;
+const COARSE_FAST_MIN_REDUCTION: usize = 2;
 const DEFAULT_NUM_PASSES: usize = 2;
 const FAST_NUM_PASSES: usize = 1;
+const DEFAULT_REUSE: usize = 0;
 
 /// Minimize a program
 #[derive(Parser, Debug)]
@@ -71,11 +80,34 @@ const FAST_NUM_PASSES: usize = 1;
           group(ArgGroup::new("fast-xor-slow").arg("fast").arg("slow")),
           group(ArgGroup::new("passes-xor-stable").arg("passes").arg("stable")))]
 pub struct Args {
-    /// Source code to consume; if empty, parse from stdin
-    #[arg(
-        short, long, default_value = None, value_name = "FILE")]
-    pub source: Option<String>,
+    /// Source code to consume; if empty, parse from stdin. Pass more than once
+    /// to reduce several files together.
+    #[arg(short, long, value_name = "FILE")]
+    pub source: Vec<String>,
     // todo: pathbuf, default_value_os_t
+    /// Reduce all of the source files in this directory (see --extension),
+    /// checking each candidate in a copy of it
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["source", "inline_includes"])]
+    pub dir: Option<PathBuf>,
+
+    /// Extension of the files to reduce in --dir, defaults to the language's
+    #[arg(long, value_name = "EXT", requires = "dir")]
+    pub extension: Vec<String>,
+
+    /// Reduce each file in this directory separately (e.g., a fuzzer's
+    /// crashers), write the results and a summary to the --output directory,
+    /// and group the inputs whose results are (nearly) the same
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["source", "dir", "inline_includes"])]
+    pub batch: Option<PathBuf>,
+
+    /// Replace `#include "..."` with the included file before reducing
+    #[arg(long, default_value_t = false)]
+    pub inline_includes: bool,
+
+    /// Directory to search for included files, see --inline-includes
+    #[arg(short = 'I', long, value_name = "DIR", requires = "inline_includes")]
+    pub include_dir: Vec<PathBuf>,
+
     /// Behavior on parse errors
     #[arg(long, default_value_t = OnParseError::Warn, value_name = "CHOICE")]
     on_parse_error: OnParseError,
@@ -88,7 +120,9 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     pub json: bool,
 
-    /// File to output, use '-' for stdout
+    /// File to output, use '-' for stdout; with several sources, a directory
+    /// to put the reduced files in; with --dir, a directory to put a copy of
+    /// the reduced project in
     #[arg(short, long, default_value = "treereduce.out")]
     pub output: String,
 
@@ -177,7 +211,8 @@ pub struct Args {
     )]
     pub timeout: Option<u64>,
 
-    /// Same as --passes 1, --min-reduction 4
+    /// Same as --passes 1, --min-reduction 4 (or 2 with --metric lines or
+    /// nodes)
     #[arg(help_heading = "Reduction options", long, default_value_t = false)]
     pub fast: bool,
 
@@ -189,24 +224,265 @@ pub struct Args {
     #[arg(help_heading = "Reduction options", long, default_value_t = DEFAULT_NUM_PASSES)]
     pub passes: usize,
 
-    /// Minimum size reduction to attempt
+    /// Minimum size reduction to attempt, see --metric [default: 2, or 1 with
+    /// --metric lines or nodes]
+    #[arg(help_heading = "Reduction options", long, value_name = "SIZE")]
+    pub min_reduction: Option<usize>,
+
+    /// Run passes until no progress is made - may be slow
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub stable: bool,
+
+    /// JSON file describing the passes to run and their settings, overrides
+    /// the other reduction options
     #[arg(
         help_heading = "Reduction options",
         long,
-        default_value_t = DEFAULT_MIN_REDUCTION,
-        value_name = "BYTES"
+        value_name = "FILE",
+        conflicts_with_all = ["fast", "slow", "stable"]
     )]
-    pub min_reduction: usize,
+    pub pipeline: Option<String>,
 
-    /// Run passes until no progress is made - may be slow
+    /// How to measure size, for prioritizing reductions, --min-reduction, and
+    /// --stats
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_enum,
+        default_value_t = Metric::Bytes
+    )]
+    pub metric: Metric,
+
+    /// JSON file of edits to start from (checked once) and hints about where
+    /// to reduce first
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub seed: Option<String>,
+
+    /// Also try replacements synthesized for node kinds without hand-written
+    /// ones (slower to start, and makes more checks)
     #[arg(help_heading = "Reduction options", long, default_value_t = false)]
-    pub stable: bool,
+    pub default_replacements: bool,
+
+    /// How many of the smallest nodes of the same kind to try as replacements
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        default_value_t = DEFAULT_REUSE,
+        value_name = "N"
+    )]
+    pub reuse: usize,
+
+    /// Don't delete function parameters along with the matching arguments
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub no_arity: bool,
+
+    /// Don't inline functions that are called exactly once
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub no_inline: bool,
+
+    /// File of rewrite rules, written as tree-sitter queries
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub rewrites: Option<String>,
+
+    /// Command that suggests transformations, see the documentation for the
+    /// protocol (may be repeated)
+    #[arg(help_heading = "Reduction options", long, value_name = "CMD")]
+    pub plugin: Vec<String>,
+
+    /// Argument to pass to the last plugin before it (may be repeated)
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true
+    )]
+    pub plugin_arg: Vec<String>,
+
+    /// Send the parse tree to plugins
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub plugin_tree: bool,
+
+    /// Never delete or replace nodes captured by this tree-sitter query (as
+    /// `@keep`, or the whole match) (may be repeated)
+    #[arg(help_heading = "Protection options", long, value_name = "QUERY")]
+    pub keep_query: Vec<String>,
+
+    /// Never delete or replace nodes of this kind (may be repeated)
+    #[arg(help_heading = "Protection options", long, value_name = "KIND")]
+    pub keep_kind: Vec<String>,
+
+    /// Keep this range of bytes verbatim, e.g., 120-200 (0-based, end
+    /// exclusive) (may be repeated)
+    #[arg(
+        help_heading = "Protection options",
+        long,
+        value_name = "START-END",
+        value_parser = parse_span
+    )]
+    pub keep_bytes: Vec<(usize, usize)>,
+
+    /// Keep this range of lines verbatim, e.g., 10-12 (1-based, inclusive)
+    /// (may be repeated)
+    #[arg(
+        help_heading = "Protection options",
+        long,
+        value_name = "START-END",
+        value_parser = parse_span
+    )]
+    pub keep_lines: Vec<(usize, usize)>,
+
+    /// Never delete or replace comments containing this text, nor the node
+    /// that follows each one
+    #[arg(help_heading = "Protection options", long, value_name = "TEXT")]
+    pub keep_marker: Option<String>,
+
+    /// Still reduce inside nodes protected by query, kind, or marker
+    #[arg(help_heading = "Protection options", long, default_value_t = false)]
+    pub keep_reduce_inside: bool,
+
+    /// Only reduce nodes captured by this tree-sitter query (as `@focus`, or
+    /// the whole match), leaving the rest as-is (may be repeated)
+    #[arg(help_heading = "Protection options", long, value_name = "QUERY")]
+    pub focus_query: Vec<String>,
+
+    /// Only reduce this range of bytes (0-based, end exclusive) (may be
+    /// repeated)
+    #[arg(
+        help_heading = "Protection options",
+        long,
+        value_name = "START-END",
+        value_parser = parse_span
+    )]
+    pub focus_bytes: Vec<(usize, usize)>,
+
+    /// Only reduce this range of lines (1-based, inclusive) (may be repeated)
+    #[arg(
+        help_heading = "Protection options",
+        long,
+        value_name = "START-END",
+        value_parser = parse_span
+    )]
+    pub focus_lines: Vec<(usize, usize)>,
+
+    /// After reducing the focused region, reduce the rest of the program too
+    #[arg(help_heading = "Protection options", long, default_value_t = false)]
+    pub focus_then_rest: bool,
+
+    /// Delete lines and tokens inside parse errors after reducing
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub fallback: bool,
+
+    /// Delete lines, tokens, or bytes without regard to the grammar after
+    /// reducing
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_delimiter = ',',
+        value_name = "GRANULARITY"
+    )]
+    pub ddmin: Vec<reduce::Granularity>,
+
+    /// Shrink numeric and string literals after reducing
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub shrink_literals: bool,
+
+    /// Rename identifiers to short names after reducing
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub rename: bool,
+
+    /// Delete comments and normalize whitespace after reducing
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub tidy: bool,
+
+    /// After reducing, make sure no single node can be deleted or replaced, and
+    /// report the nodes that remain
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub verify_minimal: bool,
+
+    /// After reducing, find the parts of the result that can be replaced with
+    /// anything, and write the result with them marked to this file
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub generalize: Option<String>,
+
+    /// Instead of reducing, find a minimal set of changes from this (passing)
+    /// version to the --source (the failing one) that's still interesting,
+    /// and output this version with just those changes
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "dir",
+            "batch",
+            "generalize",
+            "keep_query",
+            "keep_kind",
+            "keep_bytes",
+            "keep_lines",
+            "keep_marker",
+            "keep_reduce_inside",
+            "focus_query",
+            "focus_bytes",
+            "focus_lines",
+            "focus_then_rest",
+            "seed",
+            "pipeline",
+            "ddmin",
+            "shrink_literals",
+            "rename",
+            "tidy",
+            "verify_minimal",
+            "reduce_flags",
+        ]
+    )]
+    pub passing: Option<String>,
+
+    /// Also delete the arguments of the check command between `[[` and `]]`
+    /// (e.g., compiler flags), and print the ones that remain
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["batch", "passing"]
+    )]
+    pub reduce_flags: bool,
 
     /// Interestingness check; fed test case on stdin or via '@@' file
     #[arg(value_name = "CMD", required = true, num_args = 1..)]
     pub check: Vec<String>,
 }
 
+fn parse_span(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, found {s}"))?;
+    let start = start.trim().parse().map_err(|e| format!("{e}"))?;
+    let end = end.trim().parse().map_err(|e| format!("{e}"))?;
+    if end < start {
+        return Err(format!("end of range {s} is before its start"));
+    }
+    Ok((start, end))
+}
+
+/// Byte ranges from spans of bytes and of lines
+fn byte_ranges(src: &str, bytes: &[(usize, usize)], lines: &[(usize, usize)]) -> Vec<Range<usize>> {
+    let mut starts = vec![0];
+    starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+    let line_start = |line: usize| {
+        starts
+            .get(line.saturating_sub(1))
+            .copied()
+            .unwrap_or(src.len())
+    };
+    let bytes = bytes
+        .iter()
+        .map(|(start, end)| (*start).min(src.len())..(*end).min(src.len()));
+    let lines = lines
+        .iter()
+        .map(|(start, end)| line_start(*start)..line_start(end + 1));
+    bytes.chain(lines).filter(|r| !r.is_empty()).collect()
+}
+
 fn read_file(file: &str) -> Result<String> {
     fs::read_to_string(file).with_context(|| format!("Failed to read file {file}"))
 }
@@ -235,6 +511,10 @@ fn stdin_string() -> Result<String> {
     Ok(stdin_str)
 }
 
+/// Markers around the flags to reduce, see `--reduce-flags`
+const FLAGS_START: &str = "[[";
+const FLAGS_END: &str = "]]";
+
 fn check(args: &Args) -> Result<CmdCheck> {
     if args.check.is_empty() {
         error!("Internal error: empty interestingness check!");
@@ -259,9 +539,25 @@ fn check(args: &Args) -> Result<CmdCheck> {
         Some(r) => Some(Regex::new(r).context("Invalid uninteresting stderr regex")?),
         None => None,
     };
-    Ok(CmdCheck::new(
+    let mut argv: Vec<String> = argv.iter().map(|s| (*s).clone()).collect();
+    let mut reducible = None;
+    if args.reduce_flags {
+        let (Some(start), Some(end)) = (
+            argv.iter().position(|a| a == FLAGS_START),
+            argv.iter().position(|a| a == FLAGS_END),
+        ) else {
+            bail!("--reduce-flags needs the flags to reduce to be between {FLAGS_START} and {FLAGS_END}");
+        };
+        if end < start {
+            bail!("{FLAGS_END} comes before {FLAGS_START}");
+        }
+        argv.remove(end);
+        argv.remove(start);
+        reducible = Some(start..end - 1);
+    }
+    let chk = CmdCheck::new(
         cmd.clone(),
-        argv.iter().map(|s| (*s).clone()).collect(),
+        argv,
         args.interesting_exit_code.clone(),
         args.temp_dir.clone(),
         stdout_regex,
@@ -271,7 +567,31 @@ fn check(args: &Args) -> Result<CmdCheck> {
         args.inherit_stdout,
         args.inherit_stderr,
         args.timeout.map(Duration::from_secs),
-    ))
+    );
+    Ok(match reducible {
+        Some(r) => chk.with_reducible_args(r),
+        None => chk,
+    })
+}
+
+/// Reduce the flags of the check, if requested.
+fn reduce_flags(args: &Args, chk: &mut CmdCheck, files: &[&[u8]]) -> Result<()> {
+    if args.reduce_flags {
+        *chk = reduce::reduce_flags(chk, files).context("Failed to reduce flags")?;
+    }
+    Ok(())
+}
+
+/// Print the flags of the check, if they were reduced.
+fn print_flags(args: &Args, chk: &CmdCheck) -> Result<()> {
+    if args.reduce_flags {
+        writeln!(
+            io::stderr().lock(),
+            "Minimal flags: {}",
+            chk.reducible_args().join(" ")
+        )?;
+    }
+    Ok(())
 }
 
 fn check_initial_input_is_interesting(
@@ -325,6 +645,323 @@ The last line should print 0 (or any other code passed to `--interesting-exit-co
     Ok(())
 }
 
+/// Apply a minimal set of the changes from the passing version to the failing
+/// one, and output the result.
+fn reduce_diff(
+    output: &str,
+    language: &tree_sitter::Language,
+    passing: &str,
+    failing: &Original,
+    chk: &CmdCheck,
+) -> Result<()> {
+    let src = read_file(passing)?;
+    let passing = Original::new(parse(language, &src)?, src.into_bytes());
+    let (text, changes) = reduce::reduce_diff(&passing, failing, chk)?;
+    let mut stderr = io::stderr().lock();
+    writeln!(stderr, "Changes kept: {}", changes.len())?;
+    for change in &changes {
+        let line = passing.text[..change.range.start]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count()
+            + 1;
+        writeln!(
+            stderr,
+            "  line {line}: `{}` => `{}`",
+            String::from_utf8_lossy(&passing.text[change.range.clone()]).trim(),
+            String::from_utf8_lossy(&change.text).trim(),
+        )?;
+    }
+    print_result(output, std::str::from_utf8(&text)?)
+}
+
+/// Fail if any of the flags that only make sense for a single input was passed.
+fn only_one_input(args: &Args, when: &str) -> Result<()> {
+    if !(args.keep_bytes.is_empty()
+        && args.keep_lines.is_empty()
+        && args.focus_bytes.is_empty()
+        && args.focus_lines.is_empty())
+        || args.seed.is_some()
+        || args.generalize.is_some()
+        || args.passing.is_some()
+    {
+        bail!("--keep-bytes, --keep-lines, --focus-bytes, --focus-lines, --seed, --generalize, and --passing don't work {when}");
+    }
+    if args.output == "-" {
+        bail!("--output must be a directory {when}");
+    }
+    Ok(())
+}
+
+/// The absolute path of `path` with symbolic links resolved, even if it
+/// doesn't exist yet (but its parent does, or its parent's parent, etc.)
+fn canonical(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let Some(name) = path.file_name() else {
+                return Err(e);
+            };
+            let parent = path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            Ok(canonical(parent)?.join(name))
+        }
+        result => result,
+    }
+}
+
+/// One input of a batch, in the summary
+#[derive(Serialize)]
+struct BatchInput {
+    input: String,
+    /// File name of the result, in the output directory
+    output: Option<String>,
+    start_size: Option<usize>,
+    end_size: Option<usize>,
+    /// Index of the input's group
+    group: Option<usize>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchSummary {
+    /// Unit of the sizes
+    unit: &'static str,
+    inputs: Vec<BatchInput>,
+    /// Inputs whose results are (nearly) the same
+    groups: Vec<Vec<String>>,
+}
+
+/// Reduce each file in the directory, and write the results and a summary to
+/// the output directory.
+fn reduce_batch(
+    args: &Args,
+    dir: &Path,
+    language: &tree_sitter::Language,
+    node_types: &crate::node_types::NodeTypes,
+    conf: reduce::Config<CmdCheck>,
+    pipeline: Option<reduce::Pipeline>,
+) -> Result<()> {
+    only_one_input(args, "with --batch")?;
+    let input = canonical(dir).with_context(|| format!("Failed to find {}", dir.display()))?;
+    let output = canonical(Path::new(&args.output))
+        .with_context(|| format!("Failed to find {}", args.output))?;
+    if input.starts_with(&output) || output.starts_with(&input) {
+        bail!(
+            "--output {} and --batch {} must be separate directories, neither inside the other",
+            args.output,
+            dir.display()
+        );
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && !name.starts_with('.') {
+            names.push(name.into_owned());
+        }
+    }
+    names.sort();
+
+    let mut summary = BatchSummary {
+        unit: conf.metric.unit(),
+        inputs: Vec::with_capacity(names.len()),
+        groups: Vec::new(),
+    };
+    let mut inputs = Vec::with_capacity(names.len());
+    let mut input_names = Vec::with_capacity(names.len());
+    for name in names {
+        let path = dir.join(&name);
+        let bytes =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let Ok(src) = String::from_utf8(bytes) else {
+            summary.inputs.push(BatchInput {
+                input: name,
+                output: None,
+                start_size: None,
+                end_size: None,
+                group: None,
+                error: Some("not UTF-8".to_string()),
+            });
+            continue;
+        };
+        let tree = parse(language, &src)?;
+        handle_parse_errors(&path.to_string_lossy(), &tree, &args.on_parse_error);
+        inputs.push(Original::new(tree, src.into_bytes()));
+        input_names.push(name);
+    }
+
+    let conf = conf.map_check(Cached::new);
+    let pipeline = pipeline.unwrap_or_else(|| reduce::Pipeline::from_config(&conf, passes(args)));
+    let outcomes = reduce::treereduce_batch(
+        language,
+        node_types,
+        inputs,
+        &conf,
+        &pipeline,
+        !args.no_verify,
+    );
+
+    let output = Path::new(&args.output);
+    fs::create_dir_all(output)
+        .with_context(|| format!("Failed to create output directory {}", args.output))?;
+    let mut reduced = Vec::new();
+    for (name, outcome) in input_names.into_iter().zip(outcomes) {
+        let mut input = BatchInput {
+            input: name,
+            output: None,
+            start_size: None,
+            end_size: None,
+            group: None,
+            error: None,
+        };
+        match outcome {
+            reduce::Outcome::Reduced {
+                reduced: orig,
+                stats,
+            } => {
+                fs::write(output.join(&input.input), &orig.text)
+                    .with_context(|| format!("Failed to write {}", input.input))?;
+                if args.stats {
+                    let mut stdout = io::stdout().lock();
+                    writeln!(stdout, "{}:", input.input)?;
+                    stats.write_text(&mut stdout)?;
+                }
+                input.output = Some(input.input.clone());
+                input.start_size = Some(stats.start_size);
+                input.end_size = Some(stats.end_size);
+                reduced.push((summary.inputs.len(), orig));
+            }
+            reduce::Outcome::Uninteresting => {
+                input.error = Some("not interesting".to_string());
+            }
+            reduce::Outcome::Failed(e) => {
+                let e = anyhow::Error::from(e);
+                error!("Failed to reduce {}: {e:#}", input.input);
+                input.error = Some(format!("{e:#}"));
+            }
+        }
+        summary.inputs.push(input);
+    }
+    let programs: Vec<&Original> = reduced.iter().map(|(_, o)| o).collect();
+    for (g, members) in reduce::group(&programs).into_iter().enumerate() {
+        let mut group = Vec::with_capacity(members.len());
+        for m in members {
+            let input = &mut summary.inputs[reduced[m].0];
+            input.group = Some(g);
+            group.push(input.input.clone());
+        }
+        summary.groups.push(group);
+    }
+    let summary_path = output.join("summary.json");
+    fs::write(&summary_path, serde_json::to_string_pretty(&summary)?)
+        .with_context(|| format!("Failed to write {}", summary_path.display()))?;
+
+    let mut stderr = io::stderr().lock();
+    writeln!(
+        stderr,
+        "Reduced {} of {} inputs ({} distinct checks), in {} groups:",
+        reduced.len(),
+        summary.inputs.len(),
+        conf.check.len(),
+        summary.groups.len(),
+    )?;
+    for group in &summary.groups {
+        writeln!(stderr, "  {}", group.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Reduce several files together (relative to the `project` directory, if
+/// there is one), and write the results to the output directory.
+fn reduce_files(
+    args: &Args,
+    language: tree_sitter::Language,
+    node_types: &crate::node_types::NodeTypes,
+    mut conf: reduce::Config<CmdCheck>,
+    pipeline: Option<reduce::Pipeline>,
+    project: Option<&Path>,
+    paths: &[String],
+) -> Result<crate::stats::Stats> {
+    only_one_input(args, "when reducing several files")?;
+    let mut names: Vec<&str> = Vec::with_capacity(paths.len());
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let name = Path::new(path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("Not a file: {path}"))?;
+        if project.is_none() && names.contains(&name) {
+            bail!(
+                "Each --source must have a different file name, but there are several named {name}"
+            );
+        }
+        names.push(name);
+        let full = project.map_or_else(|| PathBuf::from(path), |p| p.join(path));
+        let mut src = read_file(&full.to_string_lossy())?;
+        if args.inline_includes {
+            let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+            src = crate::preproc::inline_includes(&language, &src, dir, &args.include_dir)
+                .with_context(|| format!("Failed to inline files included by {path}"))?;
+        }
+        let tree = parse(&language, &src)?;
+        handle_parse_errors(path, &tree, &args.on_parse_error);
+        files.push(Original::new(tree, src.into_bytes()));
+    }
+    conf.check = conf.check.with_file_names(paths.to_vec());
+    if let Some(project) = project {
+        conf.check = conf.check.with_project(project.to_path_buf());
+    }
+
+    if !args.no_verify {
+        let texts: Vec<&[u8]> = files.iter().map(|f| f.text.as_slice()).collect();
+        if !conf
+            .check
+            .interesting_files(&texts)
+            .context("Failed to check that initial input was interesting")?
+        {
+            bail!("Initial test was not interesting. Check that the command refers to the files with @@ (a directory containing all of them) or @@1, @@2, etc. See the usage documentation for help: https://langston-barrett.github.io/treereduce/usage.html");
+        }
+    }
+
+    let texts: Vec<&[u8]> = files.iter().map(|f| f.text.as_slice()).collect();
+    reduce_flags(args, &mut conf.check, &texts)?;
+    let pipeline = pipeline.unwrap_or_else(|| reduce::Pipeline::from_config(&conf, passes(args)));
+    let (reduced, stats) =
+        reduce::treereduce_pipeline_files(language, node_types, files, &conf, &pipeline)?;
+    let texts: Vec<&[u8]> = reduced.iter().map(|f| f.text.as_slice()).collect();
+    reduce_flags(args, &mut conf.check, &texts)?;
+    print_flags(args, &conf.check)?;
+    if let Some(project) = project {
+        let texts: Vec<(&Path, &[u8])> = paths
+            .iter()
+            .map(|p| Path::new(p.as_str()))
+            .zip(reduced.iter().map(|o| o.text.as_slice()))
+            .collect();
+        crate::project::write(project, Path::new(&args.output), &texts)
+            .with_context(|| format!("Failed to write the project to {}", args.output))?;
+    } else {
+        fs::create_dir_all(&args.output)
+            .with_context(|| format!("Failed to create output directory {}", args.output))?;
+    }
+    for (path, orig) in paths.iter().zip(&reduced) {
+        if orig.text.is_empty() {
+            writeln!(io::stderr(), "Deleted {path}")?;
+            continue;
+        }
+        if project.is_some() {
+            continue;
+        }
+        let name = Path::new(path).file_name().unwrap_or_default();
+        let out = Path::new(&args.output).join(name);
+        fs::write(&out, &orig.text)
+            .with_context(|| format!("Failed to write {}", out.display()))?;
+    }
+    stats.write_essential(&mut io::stderr().lock(), paths)?;
+    Ok(stats)
+}
+
 #[inline]
 fn print_result(output: &str, src: &str) -> Result<()> {
     if output == "-" {
@@ -340,15 +977,40 @@ fn print_result(output: &str, src: &str) -> Result<()> {
 }
 
 #[inline]
+fn rewrites(args: &Args, language: &tree_sitter::Language) -> Result<Vec<Rewrite>> {
+    let Some(path) = &args.rewrites else {
+        return Ok(Vec::new());
+    };
+    let rules = Rewrite::parse_rules(&read_file(path)?)
+        .with_context(|| format!("Failed to parse rewrite rules in {path}"))?;
+    // Just to error out early
+    crate::rewrite::compile(&rules, language)
+        .with_context(|| format!("Invalid rewrite rule in {path}"))?;
+    Ok(rules)
+}
+
 fn min_reduction(args: &Args) -> usize {
     debug_assert!(!(args.fast && args.slow));
+    let coarse = match args.metric {
+        Metric::Lines | Metric::Nodes => true,
+        Metric::Bytes | Metric::Tokens => false,
+    };
     if args.fast {
-        return FAST_MIN_REDUCTION;
+        return if coarse {
+            COARSE_FAST_MIN_REDUCTION
+        } else {
+            FAST_MIN_REDUCTION
+        };
     }
     if args.slow {
+        // The least there is, whatever the metric
         return SLOW_MIN_REDUCTION;
     }
-    args.min_reduction
+    args.min_reduction.unwrap_or(if coarse {
+        COARSE_MIN_REDUCTION
+    } else {
+        DEFAULT_MIN_REDUCTION
+    })
 }
 
 #[inline]
@@ -391,10 +1053,35 @@ fn init_tracing(args: &Args) {
     }
 }
 
+/// Each `--plugin`, with the `--plugin-arg`s that come after it (and before
+/// the next one)
+fn plugins(args: &Args, matches: &ArgMatches) -> Result<Vec<reduce::Plugin>> {
+    let starts: Vec<usize> = matches.indices_of("plugin").into_iter().flatten().collect();
+    let mut commands: Vec<Vec<String>> = args.plugin.iter().map(|p| vec![p.clone()]).collect();
+    let arg_indices = matches.indices_of("plugin_arg").into_iter().flatten();
+    for (index, arg) in arg_indices.zip(&args.plugin_arg) {
+        let Some(plugin) = starts.iter().rposition(|start| *start < index) else {
+            bail!("--plugin-arg {arg} comes before any --plugin");
+        };
+        commands[plugin].push(arg.clone());
+    }
+    Ok(commands
+        .into_iter()
+        .map(|command| reduce::Plugin {
+            command,
+            tree: args.plugin_tree,
+        })
+        .collect())
+}
+
 #[inline]
 fn configure(
     args: &Args,
     replacements: HashMap<&'static str, &'static [&'static str]>,
+    default_replacements: HashMap<String, String>,
+    inline: Option<reduce::InlineQueries>,
+    rewrites: Vec<Rewrite>,
+    plugins: Vec<reduce::Plugin>,
 ) -> Result<reduce::Config<CmdCheck>> {
     Ok(reduce::Config {
         delete_non_optional: args.slow,
@@ -402,15 +1089,52 @@ fn configure(
         jobs: args.jobs,
         min_reduction: min_reduction(args),
         replacements,
+        default_replacements,
+        reuse: args.reuse,
+        arity: !args.no_arity,
+        inline: if args.no_inline { None } else { inline },
+        plugins,
+        keep: reduce::Keep {
+            queries: args.keep_query.clone(),
+            kinds: args.keep_kind.clone(),
+            ranges: Vec::new(),
+            marker: args.keep_marker.clone(),
+            reduce_inside: args.keep_reduce_inside,
+        },
+        focus: reduce::Focus {
+            queries: args.focus_query.clone(),
+            ranges: Vec::new(),
+            then_rest: args.focus_then_rest,
+        },
+        seed: args
+            .seed
+            .as_deref()
+            .map(|p| {
+                reduce::Seed::parse(&read_file(p)?)
+                    .with_context(|| format!("Failed to parse seed in {p}"))
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        metric: args.metric,
+        rewrites,
+        fallback: args.fallback,
+        ddmin: args.ddmin.clone(),
+        shrink_literals: args.shrink_literals,
+        rename: args.rename,
+        tidy: args.tidy,
+        verify_minimal: args.verify_minimal,
     })
 }
 
 pub fn main(
     language: tree_sitter::Language,
     node_types_json_str: &'static str,
+    extensions: &[&str],
     replacements: HashMap<&'static str, &'static [&'static str]>,
+    inline: Option<reduce::InlineQueries>,
 ) -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
     debug_assert!(args.passes == DEFAULT_NUM_PASSES || !args.stable);
     debug_assert!(!(args.fast && args.slow));
     debug_assert!(!(args.uninteresting_stdout.is_some() && args.interesting_stdout.is_none()));
@@ -418,14 +1142,94 @@ pub fn main(
 
     init_tracing(&args);
     make_temp_dir(&args.temp_dir)?;
-    let conf = configure(&args, replacements)?;
+    let node_types = crate::node_types::NodeTypes::new(node_types_json_str)?;
+    let default_replacements = if args.default_replacements {
+        crate::synth::minimal_replacements(&language, &node_types)
+    } else {
+        HashMap::new()
+    };
+    let rules = rewrites(&args, &language)?;
+    let pipeline = args
+        .pipeline
+        .as_deref()
+        .map(|p| {
+            reduce::Pipeline::parse(&read_file(p)?)
+                .with_context(|| format!("Failed to parse pipeline in {p}"))
+        })
+        .transpose()?;
+    let plugins = plugins(&args, &matches)?;
+    let mut conf = configure(
+        &args,
+        replacements,
+        default_replacements,
+        inline,
+        rules,
+        plugins,
+    )?;
+
+    if let Some(dir) = &args.batch {
+        return reduce_batch(&args, dir, &language, &node_types, conf, pipeline);
+    }
+
+    let (project, paths) = if let Some(dir) = &args.dir {
+        let extensions = if args.extension.is_empty() {
+            extensions.iter().map(ToString::to_string).collect()
+        } else {
+            args.extension.clone()
+        };
+        let paths = crate::project::files(dir, &extensions)
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        if paths.is_empty() {
+            bail!(
+                "No files with extension {} in {}",
+                extensions.join(" or "),
+                dir.display()
+            );
+        }
+        let paths = paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        (Some(dir.as_path()), paths)
+    } else {
+        (None, args.source.clone())
+    };
+    if paths.len() > 1 || project.is_some() {
+        let stats = reduce_files(
+            &args,
+            language,
+            &node_types,
+            conf,
+            pipeline,
+            project,
+            &paths,
+        )?;
+        if args.stats {
+            stats.write_text(&mut io::stdout().lock())?;
+        }
+        return Ok(());
+    }
 
-    let (path, src) = if let Some(p) = &args.source {
+    let source = args.source.first();
+    let (path, src) = if let Some(p) = source {
         (p.clone(), read_file(p)?)
     } else {
         ("<stdin>".to_string(), stdin_string()?)
     };
 
+    let src = if args.inline_includes {
+        let dir = source
+            .and_then(|p| Path::new(p).parent())
+            .unwrap_or(Path::new("."));
+        crate::preproc::inline_includes(&language, &src, dir, &args.include_dir)
+            .context("Failed to inline included files")?
+    } else {
+        src
+    };
+
+    conf.keep.ranges = byte_ranges(&src, &args.keep_bytes, &args.keep_lines);
+    conf.focus.ranges = byte_ranges(&src, &args.focus_bytes, &args.focus_lines);
+
     let tree = parse(&language, &src)?;
     handle_parse_errors(&path, &tree, &args.on_parse_error);
     if !args.no_verify {
@@ -433,17 +1237,40 @@ pub fn main(
             &conf.check,
             &tree,
             src.as_bytes(),
-            args.source.as_deref(),
+            source.map(String::as_str),
         )?;
     }
 
-    let max_passes = passes(&args);
-    let node_types = crate::node_types::NodeTypes::new(node_types_json_str)?;
     let orig = Original::new(tree, src.into_bytes());
-    let (reduced, stats) =
-        reduce::treereduce_multi_pass(language, &node_types, orig, &conf, max_passes)?;
+    if let Some(passing) = &args.passing {
+        return reduce_diff(&args.output, &language, passing, &orig, &conf.check);
+    }
+    reduce_flags(&args, &mut conf.check, &[&orig.text])?;
+    let (reduced, stats) = if let Some(pipeline) = pipeline {
+        reduce::treereduce_pipeline(language, &node_types, orig, &conf, &pipeline)?
+    } else {
+        let max_passes = passes(&args);
+        reduce::treereduce_multi_pass(language, &node_types, orig, &conf, max_passes)?
+    };
+    reduce_flags(&args, &mut conf.check, &[&reduced.text])?;
     let text = std::str::from_utf8(&reduced.text)?.to_string();
     print_result(&args.output, &text)?;
+    print_flags(&args, &conf.check)?;
+    stats.write_essential(&mut io::stderr().lock(), &[])?;
+    if let Some(path) = &args.generalize {
+        let wildcards = reduce::generalize(&reduced, &conf)?;
+        let mut stderr = io::stderr().lock();
+        writeln!(
+            stderr,
+            "{} parts of the result don't matter:",
+            wildcards.len()
+        )?;
+        for wildcard in &wildcards {
+            wildcard.write_text(&reduced.text, &mut stderr)?;
+        }
+        fs::write(path, reduce::annotate(&reduced.text, &wildcards))
+            .with_context(|| format!("Failed to write {path}"))?;
+    }
 
     if args.stats {
         // https://nnethercote.github.io/perf-book/io.html#locking
@@ -1,6 +1,7 @@
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use tree_sitter::{Node, Tree};
-use tree_sitter_edit::Editor;
+use tree_sitter::{Language, Node, Parser, Tree};
+use tree_sitter_edit::{Edit, Editor};
 
 use crate::id::NodeId;
 
@@ -100,4 +101,227 @@ impl Editor for Edits {
                 .into_bytes()
         }
     }
+
+    /// Like the default implementation, but inserts a space where an edit
+    /// would otherwise glue two tokens together, e.g., deleting `/**/` from
+    /// `unsigned/**/x`.
+    fn in_order_edits<'a>(
+        &'a self,
+        source: &'a [u8],
+        tree: &'a Tree,
+    ) -> Box<dyn Iterator<Item = Edit> + 'a> {
+        let mut edits = Vec::new();
+        let mut start = 0;
+        let mut cursor = tree.walk();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if node.start_byte() < start {
+                continue;
+            }
+            if self.has_edit(tree, &node) {
+                edits.push(Edit {
+                    position: node.start_byte(),
+                    delete: node.end_byte() - node.start_byte(),
+                    insert: self.edit(source, tree, &node),
+                });
+                start = node.end_byte();
+            } else {
+                let children: Vec<_> = node.children(&mut cursor).collect();
+                stack.extend(children.into_iter().rev());
+            }
+        }
+        separate(source, tree, &mut edits);
+        Box::new(edits.into_iter())
+    }
+}
+
+/// Don't look further than this many bytes to either side of a junction
+const MAX_JUNCTION: usize = 64;
+
+/// Forget the cached junctions once there are this many
+const MAX_CACHED: usize = 1 << 16;
+
+/// The text of the leaves of the parse of `text`
+fn lex(parser: &mut Parser, text: &[u8]) -> Vec<Vec<u8>> {
+    let Some(tree) = parser.parse(text, None) else {
+        return Vec::new();
+    };
+    let mut leaves = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.child_count() == 0 {
+            if !node.is_missing() && !node.byte_range().is_empty() {
+                leaves.push(text[node.byte_range()].to_vec());
+            }
+        } else {
+            let children: Vec<_> = node.children(&mut tree.walk()).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    leaves
+}
+
+fn is_space(b: u8) -> bool {
+    b.is_ascii_whitespace()
+}
+
+/// A parser, and which junctions it found to be glued
+struct Junctions {
+    language: Language,
+    parser: Parser,
+    glued: HashMap<(Vec<u8>, Vec<u8>), bool>,
+}
+
+thread_local! {
+    // Rendering is in the hot path, and the same junctions come up again and
+    // again in different candidates.
+    static JUNCTIONS: RefCell<Option<Junctions>> = const { RefCell::new(None) };
+}
+
+/// Would these tokens lex differently without a space between them?
+fn glued(language: &Language, left: Vec<u8>, right: Vec<u8>) -> bool {
+    if left.is_empty() || right.is_empty() {
+        return false;
+    }
+    JUNCTIONS.with_borrow_mut(|junctions| {
+        let stale = match junctions {
+            Some(j) => j.language != *language,
+            None => true,
+        };
+        if stale {
+            let mut parser = Parser::new();
+            if parser.set_language(language).is_err() {
+                return false;
+            }
+            *junctions = Some(Junctions {
+                language: language.clone(),
+                parser,
+                glued: HashMap::new(),
+            });
+        }
+        let Some(j) = junctions.as_mut() else {
+            return false;
+        };
+        if let Some(glued) = j.glued.get(&(left.clone(), right.clone())) {
+            return *glued;
+        }
+        let together = [left.as_slice(), right.as_slice()].concat();
+        let apart = [left.as_slice(), b" ", right.as_slice()].concat();
+        let glued = lex(&mut j.parser, &together) != lex(&mut j.parser, &apart);
+        if j.glued.len() >= MAX_CACHED {
+            j.glued.clear();
+        }
+        j.glued.insert((left, right), glued);
+        glued
+    })
+}
+
+/// The pieces of the output: the text between the edits, and what they insert
+fn pieces<'a>(source: &'a [u8], edits: &'a [Edit]) -> Vec<&'a [u8]> {
+    let mut pieces = Vec::with_capacity(2 * edits.len() + 1);
+    let mut start = 0;
+    for edit in edits {
+        pieces.push(&source[start..edit.position]);
+        pieces.push(edit.insert.as_slice());
+        start = edit.position + edit.delete;
+    }
+    pieces.push(&source[start..]);
+    pieces
+}
+
+/// The output right before the piece at `at`, back to the last space
+fn left_of(pieces: &[&[u8]], at: usize) -> Vec<u8> {
+    let mut left: Vec<u8> = pieces[..at]
+        .iter()
+        .rev()
+        .flat_map(|p| p.iter().rev())
+        .take(MAX_JUNCTION)
+        .take_while(|b| !is_space(**b))
+        .copied()
+        .collect();
+    left.reverse();
+    left
+}
+
+/// The output starting at the piece at `at`, up to the next space
+fn right_of(pieces: &[&[u8]], at: usize) -> Vec<u8> {
+    pieces[at..]
+        .iter()
+        .flat_map(|p| p.iter())
+        .take(MAX_JUNCTION)
+        .take_while(|b| !is_space(**b))
+        .copied()
+        .collect()
+}
+
+/// Add spaces to edits that would glue together the tokens at their edges.
+fn separate(source: &[u8], tree: &Tree, edits: &mut [Edit]) {
+    let language = tree.language().to_owned();
+    let spaces: Vec<(bool, bool)> = {
+        let pieces = pieces(source, edits);
+        (0..edits.len())
+            .map(|i| {
+                let (before, after) = (2 * i + 1, 2 * i + 2);
+                let glued_before = glued(
+                    &language,
+                    left_of(&pieces, before),
+                    right_of(&pieces, before),
+                );
+                let glued_after = !pieces[before].is_empty()
+                    && glued(&language, left_of(&pieces, after), right_of(&pieces, after));
+                (glued_before, glued_after)
+            })
+            .collect()
+    };
+    for (edit, (before, after)) in edits.iter_mut().zip(spaces) {
+        if after {
+            edit.insert.push(b' ');
+        }
+        if before {
+            edit.insert.insert(0, b' ');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(src: &str, edit: impl Fn(&Node<'_>, Edits) -> Edits) -> String {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let mut edits = Edits::new();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            edits = edit(&node, edits);
+            stack.extend(node.children(&mut tree.walk()));
+        }
+        let mut out = Vec::new();
+        tree_sitter_edit::render(&mut out, &tree, src.as_bytes(), &edits).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_separate() {
+        let omit_comments = |n: &Node<'_>, e: Edits| {
+            if n.kind() == "comment" {
+                e.omit(n)
+            } else {
+                e
+            }
+        };
+        assert_eq!(render("unsigned/**/x;", omit_comments), "unsigned x;");
+        assert_eq!(render("int f(/**/);", omit_comments), "int f();");
+        let unparenthesize = |n: &Node<'_>, e: Edits| {
+            if n.kind() == "parenthesized_expression" {
+                e.replace(n, "-c".to_string())
+            } else {
+                e
+            }
+        };
+        assert_eq!(render("int x = b-(-c);", unparenthesize), "int x = b- -c;");
+    }
 }
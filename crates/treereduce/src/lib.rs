@@ -1,10 +1,15 @@
 pub mod check;
 pub mod edits;
 mod id;
+mod metric;
 mod node_types;
 mod original;
+mod preproc;
+mod project;
 pub mod reduce;
+mod rewrite;
 mod stats;
+mod synth;
 mod versioned;
 
 #[cfg(feature = "cli")]
@@ -12,6 +17,10 @@ pub mod cli;
 
 pub use check::*;
 pub use edits::*;
+pub use metric::Metric;
 pub use node_types::*;
 pub use original::*;
+pub use preproc::*;
 pub use reduce::*;
+pub use rewrite::*;
+pub use synth::*;
@@ -0,0 +1,173 @@
+//! Ways of measuring the size of a program.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tree_sitter::{Language, Node, Parser};
+
+use crate::id::NodeId;
+use crate::original::Original;
+
+fn lines(text: &[u8]) -> usize {
+    let newlines = text.iter().filter(|b| **b == b'\n').count();
+    newlines + usize::from(text.last().is_some_and(|b| *b != b'\n'))
+}
+
+/// How to measure the size of nodes and programs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    #[default]
+    Bytes,
+    /// Leaves of the parse tree, including comments
+    Tokens,
+    /// Named nodes of the parse tree
+    Nodes,
+    /// Lines that a node touches
+    Lines,
+}
+
+impl Metric {
+    #[must_use]
+    pub fn unit(self) -> &'static str {
+        match self {
+            Metric::Bytes => "bytes",
+            Metric::Tokens => "tokens",
+            Metric::Nodes => "nodes",
+            Metric::Lines => "lines",
+        }
+    }
+
+    /// Size of just this node, not counting its children
+    fn own_size(self, node: &Node<'_>, text: &[u8]) -> usize {
+        match self {
+            Metric::Bytes => node.byte_range().len(),
+            Metric::Tokens => usize::from(node.child_count() == 0 && !node.byte_range().is_empty()),
+            Metric::Nodes => usize::from(node.is_named()),
+            Metric::Lines => lines(&text[node.byte_range()]),
+        }
+    }
+
+    /// Whether the size of a node is the sum of the sizes of its children (and
+    /// its own)
+    fn is_additive(self) -> bool {
+        matches!(self, Metric::Tokens | Metric::Nodes)
+    }
+
+    /// The size of the whole program
+    #[must_use]
+    pub fn size(self, orig: &Original) -> usize {
+        if !self.is_additive() {
+            return self.size_of_text(&orig.tree.language(), &orig.text);
+        }
+        let mut size = 0;
+        let mut stack = vec![orig.tree.root_node()];
+        while let Some(node) = stack.pop() {
+            size += self.own_size(&node, &orig.text);
+            stack.extend(node.children(&mut orig.tree.walk()));
+        }
+        size
+    }
+
+    /// The size of some text, parsing it if need be
+    #[must_use]
+    pub fn size_of_text(self, language: &Language, text: &[u8]) -> usize {
+        match self {
+            Metric::Bytes => text.len(),
+            Metric::Lines => lines(text),
+            Metric::Tokens | Metric::Nodes => {
+                let mut parser = Parser::new();
+                parser
+                    .set_language(language)
+                    .expect("Failed to set tree-sitter parser language");
+                let tree = parser.parse(text, None).expect("Failed to parse");
+                self.size(&Original::new(tree, text.to_vec()))
+            }
+        }
+    }
+}
+
+/// The size of each node in a program
+#[derive(Debug, Default)]
+pub(crate) struct Sizes {
+    sizes: HashMap<NodeId, usize>,
+}
+
+impl Sizes {
+    pub(crate) fn new(metric: Metric, orig: &Original) -> Self {
+        // Pre-order, with the index of each node's parent
+        let mut nodes: Vec<(Node<'_>, Option<usize>)> = Vec::new();
+        let mut stack = vec![(orig.tree.root_node(), None)];
+        while let Some((node, parent)) = stack.pop() {
+            let index = nodes.len();
+            nodes.push((node, parent));
+            stack.extend(
+                node.children(&mut orig.tree.walk())
+                    .map(|child| (child, Some(index))),
+            );
+        }
+        let mut totals: Vec<usize> = nodes
+            .iter()
+            .map(|(node, _)| metric.own_size(node, &orig.text))
+            .collect();
+        let mut sizes = HashMap::with_capacity(nodes.len());
+        for (index, (node, parent)) in nodes.iter().enumerate().rev() {
+            if let (true, Some(parent)) = (metric.is_additive(), parent) {
+                totals[*parent] += totals[index];
+            }
+            sizes.insert(NodeId::new(node), totals[index]);
+        }
+        Sizes { sizes }
+    }
+
+    /// Also measure the nodes of another file
+    pub(crate) fn extend(&mut self, other: Sizes) {
+        self.sizes.extend(other.sizes);
+    }
+
+    pub(crate) fn of(&self, node_id: NodeId) -> usize {
+        self.sizes.get(&node_id).copied().unwrap_or(0)
+    }
+
+    /// Total size of some disjoint nodes
+    pub(crate) fn of_all(&self, node_ids: impl IntoIterator<Item = NodeId>) -> usize {
+        node_ids.into_iter().map(|id| self.of(id)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int very_long_name = 1;\nint y;\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let root = orig.tree.root_node();
+        let first = root.child(0).unwrap();
+        let expected = [
+            (Metric::Bytes, 23),
+            (Metric::Tokens, 5),
+            (Metric::Nodes, 5),
+            (Metric::Lines, 1),
+        ];
+        for (metric, size) in expected {
+            assert_eq!(
+                Sizes::new(metric, &orig).of(NodeId::new(&first)),
+                size,
+                "{metric:?}"
+            );
+        }
+        assert_eq!(Metric::Tokens.size(&orig), 8);
+        assert_eq!(Metric::Lines.size(&orig), 2);
+        assert_eq!(
+            Metric::Tokens.size_of_text(&orig.tree.language(), src.as_bytes()),
+            8
+        );
+    }
+}
@@ -54,6 +54,7 @@ pub struct FieldInfo {
 pub struct NodeTypes {
     children: HashMap<String, Children>,
     subtypes: HashMap<String, Vec<String>>,
+    supertypes: HashMap<String, Vec<String>>,
     reverse_fields: HashMap<String, Vec<FieldInfo>>,
 }
 
@@ -77,6 +78,18 @@ impl NodeTypes {
             .iter()
             .map(|n| (n.ty.clone(), subtypes(&n.ty, &nodes)))
             .collect();
+        let mut supertypes: HashMap<String, Vec<String>> = HashMap::new();
+        for (sup, subs) in &subtypes {
+            for sub in subs.iter().skip(1) {
+                let supers = supertypes.entry(sub.clone()).or_default();
+                if !supers.contains(sup) {
+                    supers.push(sup.clone());
+                }
+            }
+        }
+        for supers in supertypes.values_mut() {
+            supers.sort();
+        }
         let mut reverse_fields = HashMap::new();
 
         // For each type of node...
@@ -112,6 +125,7 @@ impl NodeTypes {
                 .map(|n| (n.ty.clone(), n.children.clone()))
                 .collect(),
             subtypes,
+            supertypes,
             reverse_fields,
         })
     }
@@ -157,6 +171,20 @@ impl NodeTypes {
     pub fn subtypes(&self, kind: &String) -> &[String] {
         self.subtypes.get(kind).expect("Invalid node kind")
     }
+
+    /// Kinds that have `kind` as a (transitive) subtype, not including `kind`
+    #[must_use]
+    pub fn supertypes(&self, kind: &str) -> &[String] {
+        self.supertypes.get(kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Each supertype, along with its (transitive) subtypes
+    pub(crate) fn all_subtypes(&self) -> impl Iterator<Item = (&String, &[String])> {
+        self.subtypes
+            .iter()
+            .filter(|(_, subs)| subs.len() > 1)
+            .map(|(sup, subs)| (sup, &subs[1..]))
+    }
 }
 
 #[cfg(test)]
@@ -169,4 +197,11 @@ mod tests {
         assert!(nt.optional("_expression", "return_statement"));
         assert!(!nt.optional("compound_statement", "function_definition"));
     }
+
+    #[test]
+    fn test_supertypes() {
+        let nt = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        assert_eq!(nt.supertypes("binary_expression"), &["expression"]);
+        assert!(nt.supertypes("expression").is_empty());
+    }
 }
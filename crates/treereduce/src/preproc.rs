@@ -0,0 +1,188 @@
+//! Awareness of the C preprocessor.
+//
+// These work on any grammar that uses the same node kinds and fields as
+// tree-sitter-c (e.g., tree-sitter-cpp), and do nothing for other languages.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tree_sitter::{Language, Node, Parser};
+
+use crate::id::NodeId;
+use crate::original::Original;
+
+const INCLUDE: &str = "preproc_include";
+
+/// Kinds of conditional directives, e.g., `#ifdef`, and their alternatives
+const CONDITIONALS: &[&str] = &[
+    "preproc_if",
+    "preproc_ifdef",
+    "preproc_elif",
+    "preproc_elifdef",
+    "preproc_else",
+];
+
+fn resolve(path: &str, dir: &Path, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    std::iter::once(dir)
+        .chain(include_dirs.iter().map(PathBuf::as_path))
+        .map(|d| d.join(path))
+        .find(|p| p.is_file())
+}
+
+/// The `foo.h` in `#include "foo.h"`
+fn local_include<'a>(node: &Node<'_>, text: &'a str) -> Option<&'a str> {
+    let path = node.child_by_field_name("path")?;
+    if path.kind() != "string_literal" {
+        return None;
+    }
+    text[path.byte_range()].strip_prefix('"')?.strip_suffix('"')
+}
+
+fn inline_includes_rec(
+    parser: &mut Parser,
+    text: &str,
+    dir: &Path,
+    include_dirs: &[PathBuf],
+    seen: &mut HashSet<PathBuf>,
+) -> io::Result<String> {
+    let Some(tree) = parser.parse(text, None) else {
+        return Ok(text.to_string());
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.kind() != INCLUDE {
+            let children: Vec<_> = node.children(&mut tree.walk()).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        let Some(path) = local_include(&node, text).and_then(|p| resolve(p, dir, include_dirs))
+        else {
+            continue;
+        };
+        out.push_str(&text[pos..node.start_byte()]);
+        pos = node.end_byte();
+        // Like `#pragma once`
+        if !seen.insert(path.canonicalize()?) {
+            continue;
+        }
+        let included = std::fs::read_to_string(&path)?;
+        let parent = path.parent().unwrap_or(dir);
+        let inlined = inline_includes_rec(parser, &included, parent, include_dirs, seen)?;
+        out.push_str(&inlined);
+        if !inlined.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    out.push_str(&text[pos..]);
+    Ok(out)
+}
+
+/// Replace each `#include "..."` with the contents of the file it includes,
+/// recursively.
+///
+/// Paths are resolved relative to the directory of the including file (`dir`
+/// for `text`), then each of `include_dirs`. Includes that can't be found
+/// (e.g., system headers) are left alone. Each file is included at most once.
+///
+/// # Errors
+///
+/// If an included file can't be read.
+pub fn inline_includes(
+    language: &Language,
+    text: &str,
+    dir: &Path,
+    include_dirs: &[PathBuf],
+) -> io::Result<String> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(language)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    inline_includes_rec(&mut parser, text, dir, include_dirs, &mut HashSet::new())
+}
+
+/// The text of the body of a conditional directive, without the condition
+fn body(node: &Node<'_>, text: &[u8]) -> Option<String> {
+    let mut cursor = node.walk();
+    let mut start = None;
+    let mut end = None;
+    for (i, child) in node.children(&mut cursor).enumerate() {
+        let field = u32::try_from(i)
+            .ok()
+            .and_then(|i| node.field_name_for_child(i));
+        if child.is_named() && field.is_none() {
+            start = start.or(Some(child.start_byte()));
+            end = Some(child.end_byte());
+        }
+    }
+    let mut body = match (start, end) {
+        (Some(start), Some(end)) => String::from_utf8(text[start..end].to_vec()).ok()?,
+        _ => String::new(),
+    };
+    if !body.is_empty() && !body.ends_with('\n') {
+        body.push('\n');
+    }
+    Some(body)
+}
+
+/// For each `#if` or `#ifdef`, the body of each of its branches
+pub(crate) fn branches(orig: &Original) -> HashMap<NodeId, Vec<String>> {
+    let mut branches = HashMap::new();
+    let mut queue = vec![orig.tree.root_node()];
+    while let Some(node) = queue.pop() {
+        queue.extend(node.children(&mut orig.tree.walk()));
+        if node.has_error() || !CONDITIONALS[..2].contains(&node.kind()) {
+            continue;
+        }
+        let mut bodies = Vec::new();
+        let mut branch = Some(node);
+        while let Some(b) = branch {
+            if !CONDITIONALS.contains(&b.kind()) {
+                break;
+            }
+            bodies.extend(body(&b, &orig.text));
+            branch = b.child_by_field_name("alternative");
+        }
+        branches.insert(NodeId::new(&node), bodies);
+    }
+    branches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branches() {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "#ifdef A\nint a;\nint b;\n#elif B\nint c;\n#else\n#endif\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let branches: Vec<_> = branches(&orig).into_values().collect();
+        assert_eq!(
+            branches,
+            vec![vec![
+                "int a;\nint b;\n".to_string(),
+                "int c;\n".to_string(),
+                String::new()
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_inline_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let inc = dir.path().join("inc");
+        std::fs::create_dir(&inc).unwrap();
+        std::fs::write(dir.path().join("a.h"), "#include \"b.h\"\nint a;").unwrap();
+        std::fs::write(inc.join("b.h"), "int b;\n").unwrap();
+        let src = "#include <stdio.h>\n#include \"a.h\"\n#include \"b.h\"\nint main;\n";
+        let inlined =
+            inline_includes(&tree_sitter_c::LANGUAGE.into(), src, dir.path(), &[inc]).unwrap();
+        assert_eq!(inlined, "#include <stdio.h>\nint b;\nint a;\nint main;\n");
+    }
+}
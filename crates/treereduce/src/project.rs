@@ -0,0 +1,123 @@
+//! Reduce all of the source files in a project directory.
+//
+// Hidden files and directories (e.g., `.git`) are neither reduced nor copied
+// into the scratch directories where the project is checked.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+}
+
+/// Paths (relative to `dir`, in order) of the files with one of the given
+/// extensions
+#[cfg(feature = "cli")]
+pub(crate) fn files(dir: &Path, extensions: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        for entry in fs::read_dir(&d)? {
+            let path = entry?.path();
+            if is_hidden(&path) {
+                continue;
+            }
+            if path.is_dir() {
+                stack.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|e| extensions.iter().any(|x| *x == e.to_string_lossy()))
+            {
+                files.push(path.strip_prefix(dir).unwrap_or(&path).to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copy the project in `from` into `to`, except for the files in `skip`
+/// (relative paths)
+fn copy(from: &Path, to: &Path, skip: &[&Path]) -> io::Result<()> {
+    let mut stack = vec![PathBuf::new()];
+    while let Some(rel) = stack.pop() {
+        fs::create_dir_all(to.join(&rel))?;
+        for entry in fs::read_dir(from.join(&rel))? {
+            let path = rel.join(entry?.file_name());
+            if is_hidden(&path) || skip.contains(&path.as_path()) {
+                continue;
+            }
+            if from.join(&path).is_dir() {
+                stack.push(path);
+            } else {
+                fs::copy(from.join(&path), to.join(&path))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a copy of the project in `from` into `to`, with the given contents
+/// for each file, or without the ones that are empty.
+pub(crate) fn write(from: &Path, to: &Path, files: &[(&Path, &[u8])]) -> io::Result<()> {
+    let skip: Vec<&Path> = files.iter().map(|(p, _)| *p).collect();
+    copy(from, to, &skip)?;
+    for (path, text) in files {
+        if text.is_empty() {
+            continue;
+        }
+        let path = to.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, text)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> tempfile::TempDir {
+        let from = tempfile::tempdir().unwrap();
+        fs::create_dir_all(from.path().join("src/.hidden")).unwrap();
+        fs::write(from.path().join("Makefile"), "all:").unwrap();
+        fs::write(from.path().join("src/main.c"), "int main;").unwrap();
+        fs::write(from.path().join("src/util.h"), "int x;").unwrap();
+        fs::write(from.path().join("src/.hidden/skip.c"), "").unwrap();
+        from
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_files() {
+        let from = project();
+        let exts = ["c".to_string(), "h".to_string()];
+        assert_eq!(
+            files(from.path(), &exts).unwrap(),
+            [PathBuf::from("src/main.c"), PathBuf::from("src/util.h")]
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let from = project();
+        let found = [PathBuf::from("src/main.c"), PathBuf::from("src/util.h")];
+        let to = tempfile::tempdir().unwrap();
+        let texts: [(&Path, &[u8]); 2] = [(&found[0], b"int y;"), (&found[1], b"")];
+        write(from.path(), to.path(), &texts).unwrap();
+        assert_eq!(
+            fs::read_to_string(to.path().join("Makefile")).unwrap(),
+            "all:"
+        );
+        assert_eq!(
+            fs::read_to_string(to.path().join("src/main.c")).unwrap(),
+            "int y;"
+        );
+        assert!(!to.path().join("src/util.h").exists());
+        assert!(!to.path().join("src/.hidden").exists());
+    }
+}
@@ -4,31 +4,70 @@
 use std::collections::{BinaryHeap, HashMap};
 use std::fmt::Debug;
 use std::io;
+use std::ops::Range;
 use std::sync::atomic::{self, AtomicUsize};
 use std::sync::{Condvar, Mutex, RwLock, TryLockError};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use tracing::{debug, debug_span, info};
-use tree_sitter::{Node, Tree};
-use tree_sitter_edit::render;
+use tracing::{debug, debug_span, info, warn};
+use tree_sitter::{Node, QueryError, Tree};
+use tree_sitter_edit::{render, Edit, Editor};
 
-use crate::check::Check;
+use crate::check::{Check, InContext};
 use crate::edits::Edits;
 use crate::id::NodeId;
+use crate::metric::{Metric, Sizes};
 use crate::node_types::NodeTypes;
 use crate::original::Original;
+use crate::preproc;
+use crate::rewrite::{self, Rewrite};
 use crate::stats::{self, Stats};
 use crate::versioned::Versioned;
 
+mod arity;
+mod batch;
+mod ddmin;
+mod diff;
 mod error;
+mod fallback;
+mod flags;
+mod focus;
+mod generalize;
+mod inline;
+mod keep;
+mod literal;
+mod minimal;
+mod pipeline;
+mod plugin;
+mod rename;
+mod reuse;
+mod seed;
 mod task;
+mod tidy;
 
+pub use batch::{group, treereduce_batch, Outcome};
+pub use ddmin::Granularity;
+pub use diff::{reduce_diff, Change};
 use error::ReductionError;
+pub use flags::reduce_flags;
+pub use focus::Focus;
+pub use generalize::{annotate, generalize, Wildcard};
+pub use inline::InlineQueries;
+pub use keep::Keep;
+use keep::{Guard, Protection};
+pub use pipeline::{Pass, Pipeline, TreePass};
+pub use plugin::Plugin;
+use reuse::Reusable;
+pub use seed::{Seed, SeedEdit, Target};
 use task::{PrioritizedTask, Reduction, Task, TaskId};
 
 use self::error::MultiPassReductionError;
 
+/// Added to the priority of tasks for nodes inside of hints, so that they come
+/// before all of the others
+const HINT_PRIORITY: usize = usize::MAX / 2;
+
 #[inline]
 fn node_size(node: &Node<'_>) -> usize {
     debug_assert!(node.start_byte() <= node.end_byte());
@@ -150,10 +189,24 @@ where
     node_types: &'a NodeTypes,
     tasks: Tasks,
     edits: RwLock<Versioned<Edits>>,
-    orig: Original,
+    /// The files of the test case, the edits apply to all of them
+    files: Vec<Original>,
     check: &'a T,
     min_task_size: usize,
     replacements: &'a HashMap<&'static str, &'static [&'static str]>,
+    default_replacements: &'a HashMap<String, String>,
+    reusable: Reusable,
+    /// Replacements for specific nodes, e.g., from rewrite rules
+    node_replacements: HashMap<NodeId, Vec<String>>,
+    protection: Protection,
+    /// Byte ranges to reduce first
+    hints: Vec<Range<usize>>,
+    metric: Metric,
+    sizes: Sizes,
+    /// The parent of each node (other than the roots)
+    parents: HashMap<NodeId, NodeId>,
+    /// One bit for each thread that's waiting for tasks
+    idle_threads: AtomicUsize,
 }
 
 struct ThreadCtx<'a, T>
@@ -170,11 +223,11 @@ where
 {
     fn new(ctx: &'a Ctx<'_, T>) -> Self {
         let mut node_ids = HashMap::new();
-        let mut queue = vec![ctx.orig.tree.root_node()];
+        let mut queue: Vec<_> = ctx.files.iter().map(|f| f.tree.root_node()).collect();
         while let Some(node) = queue.pop() {
             node_ids.insert(NodeId::new(&node), node);
             queue.reserve(node.child_count());
-            for child in node.children(&mut ctx.orig.tree.walk()) {
+            for child in node.children(&mut node.walk()) {
                 queue.push(child);
             }
         }
@@ -190,17 +243,23 @@ impl<T> Ctx<'_, T>
 where
     T: Check + Send + Sync + 'static,
 {
-    fn render(&self, edits: &Edits) -> io::Result<(bool, Vec<u8>)> {
-        let mut text: Vec<u8> = Vec::with_capacity(self.orig.text.len() / 2);
-        let changed = render(&mut text, &self.orig.tree, &self.orig.text, edits)?;
-        Ok((changed, text))
+    /// Render each file
+    fn render(&self, edits: &Edits) -> io::Result<Vec<Vec<u8>>> {
+        self.files
+            .iter()
+            .map(|orig| {
+                let mut text: Vec<u8> = Vec::with_capacity(orig.text.len() / 2);
+                render(&mut text, &orig.tree, &orig.text, edits)?;
+                Ok(text)
+            })
+            .collect()
     }
 
     fn _parse(&self, src: &[u8]) -> Tree {
         let mut parser = tree_sitter::Parser::new();
         // TODO(lb): Incremental re-parsing
         parser
-            .set_language(&self.orig.tree.language())
+            .set_language(&self.files[0].tree.language())
             .expect("Error loading language");
         parser.parse(src, None).expect("Failed to parse")
     }
@@ -218,8 +277,72 @@ where
         Ok(task)
     }
 
+    /// Strings to try replacing a node with: the hand-written replacements for
+    /// its kind if there are any, or else the default replacements for its
+    /// kind and supertypes that are smaller than it. Either way, also the
+    /// smallest nodes of the same kind or supertype found in the program, the
+    /// results of any rewrite rules that matched this node, and (for
+    /// preprocessor conditionals) the bodies of each branch.
+    fn replacements_for(&self, node: &Node<'_>) -> Vec<String> {
+        let size = node_size(node);
+        let kinds: Vec<&str> = std::iter::once(node.kind())
+            .chain(
+                self.node_types
+                    .supertypes(node.kind())
+                    .iter()
+                    .map(String::as_str),
+            )
+            .collect();
+        let mut replaces: Vec<String> = Vec::new();
+        let mut add = |replace: &str| {
+            if !replaces.iter().any(|r| r == replace) {
+                replaces.push(replace.to_string());
+            }
+        };
+        for replace in self
+            .node_replacements
+            .get(&NodeId::new(node))
+            .into_iter()
+            .flatten()
+        {
+            if replace.len() < size {
+                add(replace);
+            }
+        }
+        if let Some(hand_written) = self.replacements.get(node.kind()) {
+            for replace in *hand_written {
+                add(replace);
+            }
+        } else {
+            for kind in &kinds {
+                if let Some(replace) = self.default_replacements.get(*kind) {
+                    if replace.len() < size {
+                        add(replace);
+                    }
+                }
+            }
+        }
+        for kind in &kinds {
+            for replace in self.reusable.get(kind) {
+                if replace.len() < size {
+                    add(replace);
+                }
+            }
+        }
+        replaces
+    }
+
+    /// Size of the node, according to the [`Metric`]
+    fn size(&self, node: &Node<'_>) -> usize {
+        self.sizes.of(NodeId::new(node))
+    }
+
     fn push_task(&self, node: &Node<'_>, task: Task) -> Result<(), ReductionError> {
-        self.push_prioritized_task(node_size(node), task)
+        let inside = |h: &Range<usize>| h.start <= node.start_byte() && node.end_byte() <= h.end;
+        if self.hints.iter().any(inside) {
+            return self.push_prioritized_task(self.size(node).saturating_add(HINT_PRIORITY), task);
+        }
+        self.push_prioritized_task(self.size(node), task)
     }
 
     fn push_prioritized_task(&self, priority: usize, task: Task) -> Result<(), ReductionError> {
@@ -235,9 +358,18 @@ where
         T: Check,
     {
         self.tasks.push_all(
-            node.children(&mut self.orig.tree.walk())
-                .filter(|child| node_size(child) > self.min_task_size)
-                .map(|child| (Task::Explore(NodeId::new(&child)), node_size(&child))),
+            node.children(&mut node.walk())
+                // With lines, a child can be as big as the nodes inside it
+                .filter(|child| self.size(child) >= self.min_task_size)
+                .map(|child| {
+                    let overlaps =
+                        |h: &Range<usize>| h.start < child.end_byte() && child.start_byte() < h.end;
+                    let mut priority = self.size(&child);
+                    if self.hints.iter().any(overlaps) {
+                        priority = priority.saturating_add(HINT_PRIORITY);
+                    }
+                    (Task::Explore(NodeId::new(&child)), priority)
+                }),
         )?;
         for _ in 0..node.child_count() {
             self.tasks.push_signal.notify_one();
@@ -245,6 +377,22 @@ where
         Ok(())
     }
 
+    /// Whether the node is already gone from the output: it or one of its
+    /// ancestors was deleted, or one of its ancestors was replaced
+    fn is_gone(&self, edits: &Edits, node_id: NodeId) -> bool {
+        if edits.should_omit_id(&node_id) {
+            return true;
+        }
+        let mut id = node_id;
+        while let Some(parent) = self.parents.get(&id) {
+            if edits.should_omit_id(parent) || edits.should_replace_id(parent) {
+                return true;
+            }
+            id = *parent;
+        }
+        false
+    }
+
     fn add_task_edit(&self, task: &Task) -> Result<Option<Versioned<Edits>>, ReductionError> {
         let edits = self.edits.read()?;
         match task {
@@ -252,8 +400,8 @@ where
                 debug_assert!(false);
                 Ok(Some(self.edits.read()?.clone()))
             }
-            Task::Reduce(Reduction::Delete(node_id)) => {
-                if edits.get().should_omit_id(node_id) {
+            Task::Reduce(Reduction::Delete(node_id) | Reduction::DeleteFile(node_id)) => {
+                if self.is_gone(edits.get(), *node_id) {
                     return Ok(None);
                 }
                 Ok(Some(edits.mutate_clone(|e| e.omit_id(*node_id))))
@@ -261,15 +409,45 @@ where
             Task::Reduce(Reduction::DeleteAll(node_ids)) => {
                 if node_ids
                     .iter()
-                    .all(|node_id| edits.get().should_omit_id(node_id))
+                    .all(|node_id| self.is_gone(edits.get(), *node_id))
                 {
                     return Ok(None);
                 }
                 Ok(Some(edits.mutate_clone(|e| e.omit_ids(node_ids))))
             }
-            Task::Reduce(Reduction::Replace { node_id, with }) => Ok(Some(
-                edits.mutate_clone(|e| e.replace_id(*node_id, with.clone())),
-            )),
+            Task::Reduce(Reduction::Replace { node_id, with }) => {
+                if self.is_gone(edits.get(), *node_id) {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    edits.mutate_clone(|e| e.replace_id(*node_id, with.clone())),
+                ))
+            }
+            Task::Reduce(Reduction::ReplaceAll(replacements)) => {
+                if replacements
+                    .iter()
+                    .all(|(node_id, _)| self.is_gone(edits.get(), *node_id))
+                {
+                    return Ok(None);
+                }
+                Ok(Some(edits.mutate_clone(|e| {
+                    replacements
+                        .iter()
+                        .fold(e, |e, (node_id, with)| e.replace_id(*node_id, with.clone()))
+                })))
+            }
+            Task::Reduce(Reduction::Inline {
+                call,
+                definition,
+                with,
+            }) => {
+                if self.is_gone(edits.get(), *definition) || self.is_gone(edits.get(), *call) {
+                    return Ok(None);
+                }
+                Ok(Some(edits.mutate_clone(|e| {
+                    e.omit_id(*definition).replace_id(*call, with.clone())
+                })))
+            }
         }
     }
 
@@ -302,7 +480,8 @@ where
             // if !self.edits.read()?.old_version(&edits) {
             //     return Ok(InterestingCheck::TryAgain);
             // }
-            let (_changed, rendered) = self.render(edits.get())?;
+            let rendered = self.render(edits.get())?;
+            let texts: Vec<&[u8]> = rendered.iter().map(Vec::as_slice).collect();
 
             // For debugging:
             // let s = std::str::from_utf8(&rendered).unwrap();
@@ -312,7 +491,7 @@ where
             // let reparsed = self.parse(&rendered);
             // assert!({
             //     if reparsed.root_node().has_error() {
-            //         self.orig.tree.root_node().has_error()
+            //         self.files[0].tree.root_node().has_error()
             //     } else {
             //         true
             //     }
@@ -321,7 +500,7 @@ where
             // Wait for the process to finish, exit early (try this reduction again)
             // if another thread beat us to it.
 
-            let state = self.check.start(&rendered)?;
+            let state = self.check.start_files(&texts)?;
 
             // TODO(lb): Why is this slow?
             // while self.check.try_wait(&mut state)?.is_none() {
@@ -369,8 +548,18 @@ where
                             continue;
                         }
                         *w = edits;
-                        let size = rendered.len();
-                        info!(id, kind, priority, size, "Reduced to size: {}", size);
+                        drop(w);
+                        // Measuring may mean parsing, so only do it if it's logged
+                        if tracing::enabled!(tracing::Level::INFO) {
+                            let size = rendered
+                                .iter()
+                                .zip(&self.files)
+                                .map(|(text, orig)| {
+                                    self.metric.size_of_text(&orig.tree.language(), text)
+                                })
+                                .sum::<usize>();
+                            info!(id, kind, priority, size, "Reduced to size: {}", size);
+                        }
                         debug!(
                             event = "interesting",
                             id,
@@ -378,7 +567,7 @@ where
                             priority,
                             "Interesting {}, new minimal program:\n{}",
                             kind,
-                            std::str::from_utf8(&rendered).unwrap_or("<not UTF-8>")
+                            String::from_utf8_lossy(&rendered.concat())
                         );
                         return Ok(Interesting::Yes);
                     }
@@ -406,18 +595,24 @@ fn explore<T: Check + Send + Sync>(
     let node = tctx.find(node_id);
     let _span = debug_span!("Exploring", id = node_id.get());
     debug!("Exploring {}...", tctx.find(node_id).kind());
-    if let Some(replaces) = tctx.ctx.replacements.get(node.kind()) {
-        // TODO(lb): Benchmark locking tasks and pushing all at once
-        for replace in *replaces {
-            tctx.ctx.push_task(
-                &node,
-                Task::Reduce(Reduction::Replace {
-                    node_id,
-                    with: String::from(*replace),
-                }),
-            )?;
+    let protection = &tctx.ctx.protection;
+    if protection.is_frozen(node_id) {
+        return Ok(());
+    }
+    {
+        let edits = tctx.ctx.edits.read()?;
+        if edits.get().should_omit_id(&node_id) || edits.get().should_replace_id(&node_id) {
+            return Ok(());
         }
     }
+    if protection.is_fixed(node_id) {
+        return tctx.ctx.push_explore_children(node);
+    }
+    // TODO(lb): Benchmark locking tasks and pushing all at once
+    for with in tctx.ctx.replacements_for(&node) {
+        tctx.ctx
+            .push_task(&node, Task::Reduce(Reduction::Replace { node_id, with }))?;
+    }
     if tctx.ctx.node_types.optional_node(&node) || tctx.ctx.delete_non_optional {
         tctx.ctx
             .push_task(&node, Task::Reduce(Reduction::Delete(node_id)))?;
@@ -431,10 +626,10 @@ fn explore<T: Check + Send + Sync>(
                 let mut batch = Vec::new();
                 let mut batch_size = 0;
                 for subkind in tctx.ctx.node_types.subtypes(&node_kind) {
-                    for child in node.children(&mut tctx.ctx.orig.tree.walk()) {
-                        if child.kind() == subkind {
+                    for child in node.children(&mut node.walk()) {
+                        if child.kind() == subkind && !protection.is_fixed(NodeId::new(&child)) {
                             batch.push(NodeId::new(&child));
-                            batch_size += child.end_byte() - child.start_byte();
+                            batch_size += tctx.ctx.size(&child);
                         }
                     }
                 }
@@ -469,7 +664,20 @@ fn dispatch<T: Check + Send + Sync>(
                 Interesting::Stale => Ok(()),
             }
         }
-        Task::Reduce(Reduction::DeleteAll(_)) => {
+        Task::Reduce(Reduction::DeleteFile(node_id)) => {
+            let _span = debug_span!("Reducing", id = node_id.get());
+            match tctx.ctx.interesting(&ptask)? {
+                Interesting::Yes | Interesting::Stale => Ok(()),
+                // Reduce the file as usual
+                Interesting::No => {
+                    let root = tctx.find(node_id);
+                    tctx.ctx.push_task(&root, Task::Explore(node_id))
+                }
+            }
+        }
+        Task::Reduce(
+            Reduction::DeleteAll(_) | Reduction::ReplaceAll(_) | Reduction::Inline { .. },
+        ) => {
             // No need to check whether it was interesting, because the children will be
             // individually handled by `delete`.
             let _ = tctx.ctx.interesting(&ptask)?;
@@ -502,28 +710,21 @@ fn work<T: Check + Send + Sync>(
         num_threads < 64,
         "treereduce does not support 64 or more threads"
     );
-    static IDLE_THREADS: AtomicUsize = AtomicUsize::new(0);
-
+    let idle_threads = &ctx.idle_threads;
     let thread_bit = 1usize << thread_no;
     let all_idle_mask = (1usize << num_threads) - 1;
 
-    // Since IDLE_THREADS is static, clear this thread's bit for second and later passes
-    let idle_bits = IDLE_THREADS.load(atomic::Ordering::SeqCst);
-    if idle_bits & thread_bit != 0 {
-        IDLE_THREADS.fetch_and(!thread_bit, atomic::Ordering::SeqCst);
-    }
-
     let tctx = ThreadCtx::new(ctx);
     let mut idle = false;
     // Quit if all threads are idle and there are no remaining tasks
-    while IDLE_THREADS.load(atomic::Ordering::SeqCst) != all_idle_mask {
+    while idle_threads.load(atomic::Ordering::SeqCst) != all_idle_mask {
         if idle {
             // TODO(lb): Integrate waiting into pop?
             // TODO(lb): Benchmark the duration
             // let point_o_one_seconds = Duration::new(0, 10000000);
             let not_long = Duration::new(0, 100_000);
             tctx.ctx.tasks.wait_for_push(not_long)?;
-            let idle_bits = IDLE_THREADS.fetch_and(!thread_bit, atomic::Ordering::SeqCst);
+            let idle_bits = idle_threads.fetch_and(!thread_bit, atomic::Ordering::SeqCst);
             debug!("Thread {} waking up!", thread_no);
             assert!(idle_bits & thread_bit != 0);
             let num_idle = idle_bits.count_ones();
@@ -539,7 +740,7 @@ fn work<T: Check + Send + Sync>(
             );
             dispatch(&tctx, ptask)?;
         }
-        let idle_bits = IDLE_THREADS.fetch_or(thread_bit, atomic::Ordering::SeqCst);
+        let idle_bits = idle_threads.fetch_or(thread_bit, atomic::Ordering::SeqCst);
         let num_idle = (idle_bits | thread_bit).count_ones() as usize;
         debug!(
             idle = num_idle,
@@ -560,9 +761,132 @@ pub struct Config<T> {
     pub check: T,
     pub delete_non_optional: bool,
     pub jobs: usize,
-    // TODO(lb): Maybe per-pass, benchmark
     pub min_reduction: usize,
+    /// Hand-written replacements, these override the defaults
     pub replacements: HashMap<&'static str, &'static [&'static str]>,
+    /// Replacements for node kinds that have no hand-written ones, see
+    /// [`crate::minimal_replacements`]
+    pub default_replacements: HashMap<String, String>,
+    /// How many of the smallest nodes of the same kind in the program to try
+    /// as replacements for each node
+    pub reuse: usize,
+    /// Delete parameters along with the matching arguments at each call
+    pub arity: bool,
+    /// Queries for finding functions to inline, if the language has them
+    pub inline: Option<InlineQueries>,
+    /// External commands that suggest transformations
+    pub plugins: Vec<Plugin>,
+    /// Parts of the program to protect from reduction
+    pub keep: Keep,
+    /// Part of the program to reduce, if not all of it
+    pub focus: Focus,
+    /// Edits to start the first tree pass from, and where to reduce first
+    pub seed: Seed,
+    /// How to measure size, for priorities, `min_reduction`, and statistics
+    pub metric: Metric,
+    /// User-supplied rewrite rules
+    pub rewrites: Vec<Rewrite>,
+    /// Delta-debug lines and tokens inside parse errors after the other passes
+    pub fallback: bool,
+    /// Delta-debug the whole text at each of these granularities (in order,
+    /// until no progress is made) after the other passes
+    pub ddmin: Vec<Granularity>,
+    /// Shrink numeric and string literals after the other passes
+    pub shrink_literals: bool,
+    /// Rename identifiers to short names after the other passes
+    pub rename: bool,
+    /// Delete comments and normalize whitespace after the other passes
+    pub tidy: bool,
+    /// Make sure that no single node can be deleted or replaced after the
+    /// other passes
+    pub verify_minimal: bool,
+}
+
+impl<T> Config<T> {
+    /// The defaults of the command-line interface: one job, and only the
+    /// passes that are on by default
+    pub fn new(check: T) -> Self {
+        Config {
+            check,
+            delete_non_optional: false,
+            jobs: 1,
+            min_reduction: 2,
+            replacements: HashMap::new(),
+            default_replacements: HashMap::new(),
+            reuse: 0,
+            arity: true,
+            inline: None,
+            plugins: Vec::new(),
+            keep: Keep::default(),
+            focus: Focus::default(),
+            seed: Seed::default(),
+            metric: Metric::default(),
+            rewrites: Vec::new(),
+            fallback: false,
+            ddmin: Vec::new(),
+            shrink_literals: false,
+            rename: false,
+            tidy: false,
+            verify_minimal: false,
+        }
+    }
+
+    /// The same configuration, with a different check
+    pub fn map_check<U>(self, f: impl FnOnce(T) -> U) -> Config<U> {
+        // Not `..self`, which can't change the type of the check
+        let Config {
+            check,
+            delete_non_optional,
+            jobs,
+            min_reduction,
+            replacements,
+            default_replacements,
+            reuse,
+            arity,
+            inline,
+            plugins,
+            keep,
+            focus,
+            seed,
+            metric,
+            rewrites,
+            fallback,
+            ddmin,
+            shrink_literals,
+            rename,
+            tidy,
+            verify_minimal,
+        } = self;
+        Config {
+            check: f(check),
+            delete_non_optional,
+            jobs,
+            min_reduction,
+            replacements,
+            default_replacements,
+            reuse,
+            arity,
+            inline,
+            plugins,
+            keep,
+            focus,
+            seed,
+            metric,
+            rewrites,
+            fallback,
+            ddmin,
+            shrink_literals,
+            rename,
+            tidy,
+            verify_minimal,
+        }
+    }
+}
+
+impl<T: Default> Default for Config<T> {
+    fn default() -> Self {
+        Config::new(T::default())
+    }
 }
 
 pub fn treereduce<T: Check + Debug + Send + Sync + 'static>(
@@ -570,29 +894,171 @@ pub fn treereduce<T: Check + Debug + Send + Sync + 'static>(
     orig: Original,
     conf: &Config<T>,
 ) -> Result<(Original, Edits), ReductionError> {
-    if orig.text.is_empty() {
-        return Ok((orig, Edits::new()));
+    let (mut files, edits) = treereduce_files(node_types, vec![orig], conf)?;
+    debug_assert!(files.len() == 1);
+    Ok((files.remove(0), edits))
+}
+
+/// Total size of the files
+fn total_size(metric: Metric, files: &[Original]) -> usize {
+    files.iter().map(|orig| metric.size(orig)).sum()
+}
+
+/// Like [`treereduce`], for test cases made of several files, which are
+/// passed to the check together (see [`Check::start_files`]). All of their
+/// tasks share one heap, and each file may be deleted entirely (i.e., made
+/// empty). The byte ranges in `conf.keep` refer to the first file, and the
+/// seed is only used if there's just one file.
+pub fn treereduce_files<T: Check + Debug + Send + Sync + 'static>(
+    node_types: &NodeTypes,
+    files: Vec<Original>,
+    conf: &Config<T>,
+) -> Result<(Vec<Original>, Edits), ReductionError> {
+    let frozen = resolve(&conf.keep, &files)?;
+    reduce_files(node_types, files, conf, &frozen)
+}
+
+/// What to protect in each file
+fn resolve(keep: &Keep, files: &[Original]) -> Result<Vec<Vec<Range<usize>>>, QueryError> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, orig)| keep.resolve(orig, index))
+        .collect()
+}
+
+/// Like [`treereduce_files`], keeping the given byte ranges of each file
+/// verbatim rather than the ones in `conf.keep`
+fn reduce_files<T: Check + Debug + Send + Sync + 'static>(
+    node_types: &NodeTypes,
+    files: Vec<Original>,
+    conf: &Config<T>,
+    frozen: &[Vec<Range<usize>>],
+) -> Result<(Vec<Original>, Edits), ReductionError> {
+    if files.iter().all(|orig| orig.text.is_empty()) {
+        return Ok((files, Edits::new()));
     }
 
     let _span = debug_span!("Pass");
-    info!("Original size: {}", orig.text.len());
+    info!(
+        "Original size: {} {}",
+        total_size(conf.metric, &files),
+        conf.metric.unit()
+    );
     // eprintln!("{}", orig.tree.root_node().to_sexp());
     // TODO(#25): SIGHUP handler to save intermediate progress
     let jobs = std::cmp::max(1, conf.jobs);
     let min_reduction = std::cmp::max(1, conf.min_reduction);
     let tasks = Tasks::new();
-    let root = orig.tree.root_node();
-    let root_id = NodeId::new(&root);
-    tasks.push(Task::Explore(root_id), node_size(&root))?;
+    let mut protection = Protection::default();
+    let mut sizes = Sizes::default();
+    let mut parents = HashMap::new();
+    for (index, orig) in files.iter().enumerate() {
+        protection.extend(Protection::new(orig, &frozen[index]));
+        sizes.extend(Sizes::new(conf.metric, orig));
+        let mut queue = vec![orig.tree.root_node()];
+        while let Some(node) = queue.pop() {
+            for child in node.children(&mut node.walk()) {
+                parents.insert(NodeId::new(&child), NodeId::new(&node));
+                queue.push(child);
+            }
+        }
+    }
+    let (initial, hints) = match files.as_slice() {
+        [orig] => (
+            conf.seed.edits(orig, &conf.check, &protection)?,
+            conf.seed.hints(orig)?,
+        ),
+        _ => {
+            if conf.seed != Seed::default() {
+                warn!("Ignoring the seed, which only applies to a single file");
+            }
+            (Edits::new(), Vec::new())
+        }
+    };
+    // Like `Ctx::push_prioritized_task`
+    let seed = |task, size| {
+        if size < min_reduction {
+            return Ok(());
+        }
+        tasks.push(task, size)
+    };
+    let mut reusable = Reusable::default();
+    let mut node_replacements = HashMap::new();
+    let rules = rewrite::compile(&conf.rewrites, &files[0].tree.language())?;
+    for orig in &files {
+        let root_id = NodeId::new(&orig.tree.root_node());
+        if files.len() > 1 && !protection.is_fixed(root_id) {
+            seed(
+                Task::Reduce(Reduction::DeleteFile(root_id)),
+                sizes.of(root_id),
+            )?;
+        } else {
+            seed(Task::Explore(root_id), sizes.of(root_id))?;
+        }
+        let arity_reductions = if conf.arity {
+            arity::arity_reductions(orig)
+        } else {
+            Vec::new()
+        };
+        for node_ids in arity_reductions {
+            if node_ids.iter().any(|id| protection.is_fixed(*id)) {
+                continue;
+            }
+            let size = sizes.of_all(node_ids.iter().copied());
+            seed(Task::Reduce(Reduction::DeleteAll(node_ids)), size)?;
+        }
+        if let Some(queries) = &conf.inline {
+            for inlining in inline::inlinings(orig, queries)? {
+                if protection.is_fixed(inlining.call) || protection.is_fixed(inlining.definition) {
+                    continue;
+                }
+                seed(
+                    Task::Reduce(Reduction::Inline {
+                        call: inlining.call,
+                        definition: inlining.definition,
+                        with: inlining.with,
+                    }),
+                    sizes.of(inlining.definition),
+                )?;
+            }
+        }
+        reusable.add(&orig.tree, &orig.text, node_types, conf.reuse);
+        for plugin in &conf.plugins {
+            for replacements in plugin::candidates(orig, plugin)? {
+                if replacements.iter().any(|(id, _)| protection.is_fixed(*id)) {
+                    continue;
+                }
+                let size = sizes.of_all(replacements.iter().map(|(id, _)| *id));
+                seed(Task::Reduce(Reduction::ReplaceAll(replacements)), size)?;
+            }
+        }
+        node_replacements.extend(rewrite::rewrites(&rules, &orig.tree, &orig.text));
+        for (node_id, bodies) in preproc::branches(orig) {
+            node_replacements
+                .entry(node_id)
+                .or_insert_with(Vec::new)
+                .extend(bodies);
+        }
+    }
     let ctx = Ctx {
         delete_non_optional: conf.delete_non_optional,
         node_types,
         tasks,
-        edits: RwLock::new(Versioned::new(Edits::new())),
-        orig,
+        edits: RwLock::new(Versioned::new(initial)),
+        files,
         check: &conf.check,
         min_task_size: min_reduction,
         replacements: &conf.replacements,
+        default_replacements: &conf.default_replacements,
+        reusable,
+        node_replacements,
+        protection,
+        hints,
+        metric: conf.metric,
+        sizes,
+        parents,
+        idle_threads: AtomicUsize::new(0),
     };
 
     thread::scope(|s| {
@@ -604,7 +1070,7 @@ pub fn treereduce<T: Check + Debug + Send + Sync + 'static>(
 
     debug_assert!(ctx.tasks.heap.read()?.is_empty());
     let edits = ctx.edits.read()?.clone();
-    Ok((ctx.orig, edits.extract()))
+    Ok((ctx.files, edits.extract()))
 }
 
 // Don't care about parse errors, we're maintaining the interestingness
@@ -616,19 +1082,162 @@ fn parse(language: &tree_sitter::Language, code: &str) -> Tree {
     parser.parse(code, None).expect("Failed to parse code")
 }
 
+/// Run a pass that doesn't use the task heap, and re-parse the result.
+fn post_pass(
+    language: &tree_sitter::Language,
+    orig: Original,
+    stats: &mut Stats,
+    pass: impl FnOnce(&Original) -> Result<Edits, ReductionError>,
+) -> Result<Original, MultiPassReductionError> {
+    post_pass_text(language, orig, stats, |o| {
+        let edits = pass(o)?;
+        let mut new_src = Vec::new();
+        render(&mut new_src, &o.tree, o.text.as_slice(), &edits)?;
+        Ok(new_src)
+    })
+}
+
+/// Like [`post_pass`], for passes that produce new text rather than edits.
+fn post_pass_text(
+    language: &tree_sitter::Language,
+    orig: Original,
+    stats: &mut Stats,
+    pass: impl FnOnce(&Original) -> Result<Vec<u8>, ReductionError>,
+) -> Result<Original, MultiPassReductionError> {
+    let pass_start_size = stats.metric.size(&orig);
+    let pass_start = Instant::now();
+    let new_src = pass(&orig)?;
+    let text = std::str::from_utf8(&new_src)?.to_string();
+    let orig = Original::new(parse(language, &text), new_src);
+    stats.passes.push(stats::Pass {
+        duration: pass_start.elapsed(),
+        metric: stats.metric,
+        start_size: pass_start_size,
+        end_size: stats.metric.size(&orig),
+    });
+    Ok(orig)
+}
+
 pub fn treereduce_multi_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
     language: tree_sitter::Language,
     node_types: &NodeTypes,
-    mut orig: Original,
+    orig: Original,
     conf: &Config<T>,
     max_passes: Option<usize>,
 ) -> Result<(Original, Stats), MultiPassReductionError> {
+    let pipeline = Pipeline::from_config(conf, max_passes);
+    treereduce_pipeline(language, node_types, orig, conf, &pipeline)
+}
+
+/// Run each pass of the pipeline in order (and repeatedly, if it loops).
+pub fn treereduce_pipeline<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    orig: Original,
+    conf: &Config<T>,
+    pipeline: &Pipeline,
+) -> Result<(Original, Stats), MultiPassReductionError> {
+    let (mut files, stats) =
+        treereduce_pipeline_files(language, node_types, vec![orig], conf, pipeline)?;
+    debug_assert!(files.len() == 1);
+    Ok((files.remove(0), stats))
+}
+
+/// Like [`treereduce_pipeline`], for test cases made of several files, see
+/// [`treereduce_files`]. Files that were deleted entirely are empty.
+pub fn treereduce_pipeline_files<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut files: Vec<Original>,
+    conf: &Config<T>,
+    pipeline: &Pipeline,
+) -> Result<(Vec<Original>, Stats), MultiPassReductionError> {
+    debug_assert!(!files.is_empty());
     let mut stats = Stats::new();
-    stats.start_size = orig.text.len();
+    stats.metric = conf.metric;
+    stats.start_size = total_size(conf.metric, &files);
     let reduce_start = Instant::now();
+    let mut conf = conf.clone();
+    // Like the protected ranges, the focused ones refer to the first file
+    let outside = conf
+        .focus
+        .outside(&files[0])
+        .map_err(ReductionError::Query)?;
+    let then_rest = conf.focus.then_rest && !outside.is_empty();
+    let mut frozen = resolve(&conf.keep, &files).map_err(ReductionError::Query)?;
+    let focused = outside.len();
+    frozen[0].extend(outside);
+    conf.focus = Focus::default();
+    files = run_pipeline(
+        &language,
+        node_types,
+        files,
+        &mut conf,
+        &mut frozen,
+        pipeline,
+        &mut stats,
+    )?;
+    if then_rest {
+        info!("Reducing the rest of the program");
+        // The ranges outside of the focus come after the protected ones
+        let kept = frozen[0].len().saturating_sub(focused);
+        frozen[0].truncate(kept);
+        files = run_pipeline(
+            &language,
+            node_types,
+            files,
+            &mut conf,
+            &mut frozen,
+            pipeline,
+            &mut stats,
+        )?;
+    }
+    stats.duration = reduce_start.elapsed();
+    info!("Total time: {}ms", stats.duration.as_millis());
+    stats.end_size = total_size(conf.metric, &files);
+    Ok((files, stats))
+}
+
+/// Run each pass of the pipeline in order (and repeatedly, if it loops).
+fn run_pipeline<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut files: Vec<Original>,
+    conf: &mut Config<T>,
+    frozen: &mut [Vec<Range<usize>>],
+    pipeline: &Pipeline,
+    stats: &mut Stats,
+) -> Result<Vec<Original>, MultiPassReductionError> {
+    loop {
+        let start_size = total_size(conf.metric, &files);
+        for pass in &pipeline.passes {
+            files = run_pass(language, node_types, files, conf, frozen, pass, stats)?;
+            if let Pass::Tree(_) = pass {
+                conf.seed = Seed::default();
+            }
+        }
+        if !pipeline.fixpoint || total_size(conf.metric, &files) >= start_size {
+            break;
+        }
+        info!("Repeating pipeline");
+    }
+    Ok(files)
+}
+
+fn tree_passes<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut files: Vec<Original>,
+    conf: &Config<T>,
+    frozen: &mut [Vec<Range<usize>>],
+    tree_pass: &TreePass,
+    stats: &mut Stats,
+) -> Result<Vec<Original>, MultiPassReductionError> {
+    let mut conf = tree_pass.configure(conf);
+    let max_passes = tree_pass.passes;
     let mut passes_done = 0;
     while passes_done < max_passes.unwrap_or(usize::MAX) {
-        let pass_start_size = orig.text.len();
+        let pass_start_size = total_size(conf.metric, &files);
         info!(
             "Starting pass {} / {}",
             passes_done + 1,
@@ -636,18 +1245,31 @@ pub fn treereduce_multi_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
         );
         let pass_start = Instant::now();
 
-        let (new, edits) = treereduce(node_types, orig, conf)?;
-        orig = new;
-        let mut new_src = Vec::new();
-        render(&mut new_src, &orig.tree, orig.text.as_slice(), &edits)?;
-        let text = std::str::from_utf8(&new_src)?.to_string();
-        orig = Original::new(parse(&language, &text), new_src);
+        let (new, edits) = reduce_files(node_types, files, &conf, frozen)?;
+        // The seed refers to the original text
+        conf.seed = Seed::default();
+        files = Vec::with_capacity(new.len());
+        for (index, orig) in new.into_iter().enumerate() {
+            if !frozen[index].is_empty() {
+                let applied: Vec<Edit> = edits.in_order_edits(&orig.text, &orig.tree).collect();
+                frozen[index] = keep::shift(&frozen[index], &applied).unwrap_or_else(|| {
+                    warn!("Lost track of the protected ranges");
+                    Vec::new()
+                });
+            }
+            let mut new_src = Vec::new();
+            render(&mut new_src, &orig.tree, orig.text.as_slice(), &edits)?;
+            let text = std::str::from_utf8(&new_src)?.to_string();
+            files.push(Original::new(parse(language, &text), new_src));
+        }
 
         passes_done += 1;
+        let pass_end_size = total_size(conf.metric, &files);
         let pass_stats = stats::Pass {
             duration: pass_start.elapsed(),
+            metric: conf.metric,
             start_size: pass_start_size,
-            end_size: orig.text.len(),
+            end_size: pass_end_size,
         };
         debug!(
             "Pass {} duration: {}ms",
@@ -657,12 +1279,171 @@ pub fn treereduce_multi_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
         stats.passes.push(pass_stats);
 
         if edits.is_empty() {
-            info!("Qutting after pass {} found no reductions", passes_done);
+            info!("Quitting after pass {} found no reductions", passes_done);
+            break;
+        }
+        if pass_start_size.saturating_sub(pass_end_size) < tree_pass.min_progress {
+            info!(
+                "Quitting after pass {} made too little progress",
+                passes_done
+            );
             break;
         }
     }
-    stats.duration = reduce_start.elapsed();
-    info!("Total time: {}ms", stats.duration.as_millis());
-    stats.end_size = orig.text.len();
-    Ok((orig, stats))
+    Ok(files)
+}
+
+fn run_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut files: Vec<Original>,
+    conf: &Config<T>,
+    frozen: &mut [Vec<Range<usize>>],
+    pass: &Pass,
+    stats: &mut Stats,
+) -> Result<Vec<Original>, MultiPassReductionError> {
+    if let Pass::Tree(tree_pass) = pass {
+        return tree_passes(language, node_types, files, conf, frozen, tree_pass, stats);
+    }
+    if let Pass::VerifyMinimal = pass {
+        stats.essential = None;
+    }
+    // The other passes work on one file at a time
+    for index in 0..files.len() {
+        if files[index].text.is_empty() {
+            continue;
+        }
+        let texts: Vec<Vec<u8>> = files.iter().map(|orig| orig.text.clone()).collect();
+        let check = InContext::new(
+            &conf.check,
+            texts.iter().map(Vec::as_slice).collect(),
+            index,
+        );
+        // These passes don't know which nodes are protected
+        let check = &Guard::new(&files[index], frozen[index].clone(), &check);
+        let orig = files[index].clone();
+        files[index] = post_passes(language, orig, conf, pass, check, stats)?;
+        frozen[index] = check.frozen(&files[index].text);
+        if let Pass::VerifyMinimal = pass {
+            stats
+                .essential
+                .get_or_insert_with(Vec::new)
+                .extend(minimal::essential(&files[index], index));
+        }
+    }
+    Ok(files)
+}
+
+/// Run a pass other than the tree passes on one file.
+fn post_passes<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    mut orig: Original,
+    conf: &Config<T>,
+    pass: &Pass,
+    check: &(impl Check + Sync),
+    stats: &mut Stats,
+) -> Result<Original, MultiPassReductionError> {
+    match pass {
+        Pass::Tree(_) => unreachable!("tree passes work on all of the files at once"),
+        Pass::Fallback => {
+            if orig.tree.root_node().has_error() {
+                info!("Starting parse error passes");
+                for granularity in [Granularity::Line, Granularity::Token] {
+                    orig = post_pass_text(language, orig, stats, |o| {
+                        fallback::reduce_errors(o, check, granularity)
+                    })?;
+                }
+            }
+        }
+        Pass::Ddmin { granularities } => {
+            info!("Starting delta debugging passes");
+            loop {
+                let start_size = conf.metric.size(&orig);
+                for granularity in granularities {
+                    orig = post_pass_text(language, orig, stats, |o| {
+                        let whole = 0..o.text.len();
+                        ddmin::reduce(o, check, *granularity, std::slice::from_ref(&whole))
+                    })?;
+                }
+                if conf.metric.size(&orig) >= start_size {
+                    break;
+                }
+            }
+        }
+        Pass::ShrinkLiterals => {
+            info!("Starting literal pass");
+            orig = post_pass(language, orig, stats, |o| {
+                literal::shrink_literals(o, check)
+            })?;
+        }
+        Pass::Rename => {
+            info!("Starting renaming pass");
+            orig = post_pass(language, orig, stats, |o| rename::rename(o, check))?;
+        }
+        Pass::Tidy => {
+            info!("Starting tidying pass");
+            orig = post_pass(language, orig, stats, |o| tidy::delete_comments(o, check))?;
+            orig = post_pass_text(language, orig, stats, |o| {
+                tidy::normalize_whitespace(o, check)
+            })?;
+        }
+        Pass::VerifyMinimal => {
+            info!("Starting minimality check");
+            orig = post_pass_text(language, orig, stats, |o| {
+                minimal::reduce(o, check, &conf.replacements, &conf.default_replacements)
+            })?;
+        }
+    }
+    Ok(orig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::CmdCheck;
+
+    #[test]
+    fn test_metric_lines() {
+        let language: tree_sitter::Language = tree_sitter_c::LANGUAGE.into();
+        let node_types = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        let src = "int x;\nint y = f(1, 2);\nint z;\n";
+        let orig = Original::new(parse(&language, src), src.as_bytes().to_vec());
+        let check = CmdCheck::exit_zero("grep", &["-q", "y"]);
+        let conf = Config {
+            min_reduction: 1,
+            metric: Metric::Lines,
+            ..Config::new(check)
+        };
+        let (reduced, _) = treereduce_multi_pass(language, &node_types, orig, &conf, None).unwrap();
+        // Both the other lines and the rest of the same line are gone
+        assert_eq!(String::from_utf8(reduced.text).unwrap().trim(), "y");
+    }
+
+    #[test]
+    fn test_keep_reduce_inside() {
+        let language: tree_sitter::Language = tree_sitter_c::LANGUAGE.into();
+        let node_types = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        let src = "int helper(void) { puts(\"bye\"); return 1; }\nint main(void) { return 0; }\n";
+        let orig = Original::new(parse(&language, src), src.as_bytes().to_vec());
+        let check = CmdCheck::exit_zero("grep", &["-q", "return 0"]);
+        let conf = Config {
+            min_reduction: 1,
+            keep: Keep {
+                queries: vec![
+                    "(function_definition declarator: (_ declarator: (identifier) @name \
+                     (#eq? @name \"helper\")) body: (_) @keep)"
+                        .to_string(),
+                ],
+                reduce_inside: true,
+                ..Keep::default()
+            },
+            ..Config::new(check)
+        };
+        let (reduced, _) = treereduce_multi_pass(language, &node_types, orig, &conf, None).unwrap();
+        // The body was reduced, but not deleted along with the rest of the
+        // function
+        let text = String::from_utf8(reduced.text).unwrap();
+        assert!(!text.contains("bye"), "{text}");
+        assert_eq!(text.matches(['{', '}']).count(), 2, "{text}");
+    }
 }
@@ -0,0 +1,135 @@
+//! Delete a function's parameter along with the corresponding argument at
+//! each call site.
+//
+// Deleting just the parameter or just one argument almost always makes the
+// program ill-formed, so neither goes away on its own. Like renaming, there's
+// no scope analysis: functions and calls are matched up by name.
+
+use std::collections::BTreeMap;
+
+use tree_sitter::Node;
+
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::rename::is_word;
+
+/// Fields that hold the name of a function being defined or declared
+const NAME_FIELDS: &[&str] = &["name", "declarator"];
+
+/// Fields that hold the function being called
+const CALLEE_FIELDS: &[&str] = &["function", "name"];
+
+#[derive(Debug, Default)]
+struct Function<'tree> {
+    /// Parameter lists of each definition or declaration
+    parameters: Vec<Node<'tree>>,
+    /// Argument lists of each call
+    arguments: Vec<Node<'tree>>,
+}
+
+fn name<'a>(orig: &'a Original, node: &Node<'_>, fields: &[&str]) -> Option<&'a str> {
+    fields.iter().find_map(|field| {
+        let child = node.child_by_field_name(field)?;
+        let text = std::str::from_utf8(&orig.text[child.byte_range()]).ok()?;
+        is_word(text).then_some(text)
+    })
+}
+
+fn elements<'tree>(list: &Node<'tree>) -> Vec<Node<'tree>> {
+    list.named_children(&mut list.walk())
+        .filter(|n| !n.is_extra())
+        .collect()
+}
+
+/// The node, plus a comma to delete along with it
+fn with_separator(node: &Node<'_>) -> Vec<NodeId> {
+    let mut ids = vec![NodeId::new(node)];
+    let sep = [node.next_sibling(), node.prev_sibling()]
+        .into_iter()
+        .flatten()
+        .find(|n| n.kind() == ",");
+    if let Some(sep) = sep {
+        ids.push(NodeId::new(&sep));
+    }
+    ids
+}
+
+/// Groups of nodes to delete all together: the `i`th parameter of each
+/// definition of a function, and the `i`th argument of each call to it.
+pub(super) fn arity_reductions(orig: &Original) -> Vec<Vec<NodeId>> {
+    let mut functions: BTreeMap<&str, Function<'_>> = BTreeMap::new();
+    let mut queue = vec![orig.tree.root_node()];
+    while let Some(node) = queue.pop() {
+        queue.extend(node.children(&mut orig.tree.walk()));
+        if node.has_error() {
+            continue;
+        }
+        if let Some(params) = node.child_by_field_name("parameters") {
+            if let Some(name) = name(orig, &node, NAME_FIELDS) {
+                functions.entry(name).or_default().parameters.push(params);
+            }
+        }
+        if let Some(args) = node.child_by_field_name("arguments") {
+            if let Some(name) = name(orig, &node, CALLEE_FIELDS) {
+                functions.entry(name).or_default().arguments.push(args);
+            }
+        }
+    }
+
+    let mut reductions = Vec::new();
+    for function in functions.values() {
+        let Some(first) = function.parameters.first() else {
+            continue;
+        };
+        let arity = elements(first).len();
+        let lists: Vec<Vec<Node<'_>>> = function
+            .parameters
+            .iter()
+            .chain(&function.arguments)
+            .map(elements)
+            .collect();
+        // Overloads, variadic functions, `(void)`, and so on
+        if lists.iter().any(|l| l.len() != arity) {
+            continue;
+        }
+        for i in 0..arity {
+            let mut ids = Vec::new();
+            for list in &lists {
+                ids.extend(with_separator(&list[i]));
+            }
+            reductions.push(ids);
+        }
+    }
+    reductions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edits::Edits;
+
+    #[test]
+    fn test_arity_reductions() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int f(int a, int b);\n\
+                   int f(int a, int b) { return b; }\n\
+                   int g(void) { return f(1, 2) + f(3, 4); }\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let reductions = arity_reductions(&orig);
+        // Two for `f`, and one for `void`
+        assert_eq!(reductions.len(), 3);
+        let edits = Edits::new().omit_ids(&reductions[0]);
+        let mut out = Vec::new();
+        tree_sitter_edit::render(&mut out, &orig.tree, src.as_bytes(), &edits).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "int f( int b);\n\
+             int f( int b) { return b; }\n\
+             int g(void) { return f( 2) + f( 4); }\n"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Reduce many inputs, e.g., a fuzzer's crashers, and group the results.
+//
+// The inputs are reduced concurrently by a pool of workers that split the
+// jobs between them. Wrapping the check in a `Cached` lets the reductions share
+// verdicts, which helps when several inputs reduce to the same thing. Results
+// are grouped by a fingerprint that ignores the names of identifiers, the
+// values of literals, comments, and whitespace.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::thread;
+
+use tree_sitter::Node;
+
+use crate::check::Check;
+use crate::node_types::NodeTypes;
+use crate::original::Original;
+use crate::stats::Stats;
+
+use super::error::MultiPassReductionError;
+use super::{treereduce_pipeline, Config, Pipeline};
+
+/// What happened to one input of a batch
+#[derive(Debug)]
+pub enum Outcome {
+    Reduced {
+        reduced: Original,
+        stats: Stats,
+    },
+    /// The input wasn't interesting to begin with
+    Uninteresting,
+    Failed(MultiPassReductionError),
+}
+
+fn reduce_one<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    node_types: &NodeTypes,
+    orig: Original,
+    conf: &Config<T>,
+    pipeline: &Pipeline,
+    verify: bool,
+) -> Outcome {
+    if verify {
+        match conf.check.interesting(&orig.text) {
+            Ok(true) => (),
+            Ok(false) => return Outcome::Uninteresting,
+            Err(e) => return Outcome::Failed(e.into()),
+        }
+    }
+    match treereduce_pipeline(language.clone(), node_types, orig, conf, pipeline) {
+        Ok((reduced, stats)) => Outcome::Reduced { reduced, stats },
+        Err(e) => Outcome::Failed(e),
+    }
+}
+
+/// Reduce each input with the pipeline, several at a time. `conf.jobs` are
+/// split between the concurrent reductions. If `verify` is set, inputs that
+/// aren't interesting are skipped. The outcomes are in the same order as the
+/// inputs.
+pub fn treereduce_batch<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: &tree_sitter::Language,
+    node_types: &NodeTypes,
+    inputs: Vec<Original>,
+    conf: &Config<T>,
+    pipeline: &Pipeline,
+    verify: bool,
+) -> Vec<Outcome> {
+    let count = inputs.len();
+    let workers = conf.jobs.clamp(1, count.max(1));
+    let mut conf = conf.clone();
+    conf.jobs = std::cmp::max(1, conf.jobs / workers);
+    let inputs: Vec<Mutex<Option<Original>>> =
+        inputs.into_iter().map(|i| Mutex::new(Some(i))).collect();
+    let outcomes: Vec<Mutex<Option<Outcome>>> = (0..count).map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let index = next.fetch_add(1, atomic::Ordering::SeqCst);
+                if index >= count {
+                    break;
+                }
+                let Some(orig) = inputs[index].lock().ok().and_then(|mut i| i.take()) else {
+                    continue;
+                };
+                let outcome = reduce_one(language, node_types, orig, &conf, pipeline, verify);
+                if let Ok(mut o) = outcomes[index].lock() {
+                    *o = Some(outcome);
+                }
+            });
+        }
+    });
+    outcomes
+        .into_iter()
+        .map(|o| {
+            o.into_inner()
+                .ok()
+                .flatten()
+                .expect("Each input should have an outcome")
+        })
+        .collect()
+}
+
+/// The tokens of the program, with identifiers numbered in order of first
+/// appearance, literals replaced by their kinds, and comments left out
+fn fingerprint(orig: &Original) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut names: HashMap<&[u8], usize> = HashMap::new();
+    let mut stack: Vec<Node<'_>> = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.is_extra() || node.byte_range().is_empty() {
+            continue;
+        }
+        let kind = node.kind();
+        if kind.contains("literal") || kind == "string" || kind == "number" {
+            tokens.push(kind.to_string());
+            continue;
+        }
+        if node.child_count() > 0 {
+            let children: Vec<_> = node.children(&mut node.walk()).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        let text = &orig.text[node.byte_range()];
+        if kind.ends_with("identifier") {
+            let next = names.len();
+            let n = *names.entry(text).or_insert(next);
+            tokens.push(format!("{kind}#{n}"));
+        } else {
+            tokens.push(String::from_utf8_lossy(text).into_owned());
+        }
+    }
+    tokens
+}
+
+/// Indices of the programs that are identical or nearly so (i.e., up to the
+/// names of identifiers, the values of literals, comments, and whitespace), in
+/// order of first appearance
+#[must_use]
+pub fn group(programs: &[&Original]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_fingerprint: HashMap<Vec<String>, usize> = HashMap::new();
+    for (index, orig) in programs.iter().enumerate() {
+        let next = groups.len();
+        let group = *by_fingerprint.entry(fingerprint(orig)).or_insert(next);
+        if group == next {
+            groups.push(Vec::new());
+        }
+        groups[group].push(index);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let mut parse = |src: &str| Original::new(parser.parse(src, None).unwrap(), src.into());
+        let programs = [
+            parse("int x = 1 / 0;"),
+            parse("int f(void);"),
+            parse("int  y = 5/0; // crash"),
+            parse("int x = 1 / 0;"),
+            parse("long x = 1 / 0;"),
+        ];
+        let programs: Vec<&Original> = programs.iter().collect();
+        assert_eq!(group(&programs), [vec![0, 2, 3], vec![1], vec![4]]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Grammar-agnostic delta debugging over lines, tokens, or bytes.
+
+use std::ops::Range;
+
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::check::Check;
+use crate::original::Original;
+
+use super::error::ReductionError;
+
+/// Unit of text to delete when delta debugging
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Line,
+    /// Leaves of the parse tree
+    Token,
+    Byte,
+}
+
+impl std::fmt::Display for Granularity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Granularity::Line => write!(f, "line"),
+            Granularity::Token => write!(f, "token"),
+            Granularity::Byte => write!(f, "byte"),
+        }
+    }
+}
+
+fn lines(text: &[u8], region: &Range<usize>) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = region.start;
+    for (i, b) in text[region.clone()].iter().enumerate() {
+        if *b == b'\n' {
+            let end = region.start + i + 1;
+            lines.push(start..end);
+            start = end;
+        }
+    }
+    if start < region.end {
+        lines.push(start..region.end);
+    }
+    lines
+}
+
+fn tokens(orig: &Original, region: &Range<usize>) -> Vec<Range<usize>> {
+    let mut tokens = Vec::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let range = node.byte_range();
+        if range.is_empty() || range.end <= region.start || region.end <= range.start {
+            continue;
+        }
+        if node.child_count() == 0 {
+            tokens.push(range);
+        } else {
+            let children: Vec<_> = node.children(&mut orig.tree.walk()).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    tokens
+}
+
+/// Characters, or bytes if the region isn't valid UTF-8
+fn chars(text: &[u8], region: &Range<usize>) -> Vec<Range<usize>> {
+    match std::str::from_utf8(&text[region.clone()]) {
+        Ok(s) => s
+            .char_indices()
+            .map(|(i, c)| region.start + i..region.start + i + c.len_utf8())
+            .collect(),
+        Err(_) => region.clone().map(|i| i..i + 1).collect(),
+    }
+}
+
+fn chunks(
+    orig: &Original,
+    granularity: Granularity,
+    regions: &[Range<usize>],
+) -> Vec<Range<usize>> {
+    regions
+        .iter()
+        .flat_map(|region| match granularity {
+            Granularity::Line => lines(&orig.text, region),
+            Granularity::Token => tokens(orig, region),
+            Granularity::Byte => chars(&orig.text, region),
+        })
+        .collect()
+}
+
+/// The text, without the chunks that aren't kept
+fn without(text: &[u8], chunks: &[Range<usize>], keep: &[bool]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut pos = 0;
+    for (chunk, _) in chunks.iter().zip(keep).filter(|(_, k)| !**k) {
+        out.extend_from_slice(&text[pos..chunk.start]);
+        pos = chunk.end;
+    }
+    out.extend_from_slice(&text[pos..]);
+    out
+}
+
+/// Delta debugging (only the "reduce to complement" half): find a subset of
+/// the items that's still interesting, deleting ever-smaller groups of them.
+pub(super) fn ddmin(
+    len: usize,
+    mut interesting: impl FnMut(&[bool]) -> Result<bool, ReductionError>,
+) -> Result<Vec<bool>, ReductionError> {
+    let mut keep = vec![true; len];
+    let mut n = 2;
+    loop {
+        let live: Vec<usize> = (0..len).filter(|i| keep[*i]).collect();
+        if live.is_empty() {
+            break;
+        }
+        let n_here = std::cmp::min(n, live.len());
+        let size = live.len().div_ceil(n_here);
+        let mut reduced = false;
+        for group in live.chunks(size) {
+            let mut candidate = keep.clone();
+            for i in group {
+                candidate[*i] = false;
+            }
+            if interesting(&candidate)? {
+                debug!("Deleted {} of {} chunks", group.len(), live.len());
+                keep = candidate;
+                reduced = true;
+                n = std::cmp::max(n_here - 1, 2);
+                break;
+            }
+        }
+        if !reduced {
+            if n_here >= live.len() {
+                break;
+            }
+            n = std::cmp::min(2 * n_here, live.len());
+        }
+    }
+    Ok(keep)
+}
+
+/// Delete chunks of text inside of the given regions.
+pub(super) fn reduce<T: Check>(
+    orig: &Original,
+    check: &T,
+    granularity: Granularity,
+    regions: &[Range<usize>],
+) -> Result<Vec<u8>, ReductionError> {
+    let chunks = chunks(orig, granularity, regions);
+    if chunks.is_empty() {
+        return Ok(orig.text.clone());
+    }
+    info!(
+        "Delta debugging over {} chunks ({granularity})",
+        chunks.len()
+    );
+    let keep = ddmin(chunks.len(), |keep| {
+        Ok(check.interesting(&without(&orig.text, &chunks, keep))?)
+    })?;
+    Ok(without(&orig.text, &chunks, &keep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ddmin() {
+        let keep = ddmin(20, |keep| Ok(keep[3] && keep[17])).unwrap();
+        let kept: Vec<_> = (0..20).filter(|i| keep[*i]).collect();
+        assert_eq!(kept, vec![3, 17]);
+    }
+}
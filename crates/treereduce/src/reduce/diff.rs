@@ -0,0 +1,229 @@
+//! Reduce the difference between a passing and a failing version.
+//
+// The changes are found by diffing the parse trees: the children of two nodes
+// of the same kind are aligned by their longest common subsequence of
+// identical nodes, and each gap between the aligned children is either diffed
+// child-by-child (if the children have the same kinds) or replaced wholesale.
+// Delta debugging then finds a minimal set of the changes to apply to the
+// passing version that's still interesting.
+
+use std::ops::Range;
+
+use tracing::{info, warn};
+use tree_sitter::Node;
+
+use crate::check::Check;
+use crate::original::Original;
+
+use super::ddmin::ddmin;
+use super::error::ReductionError;
+
+/// Replace a range of the passing version with text from the failing one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub range: Range<usize>,
+    pub text: Vec<u8>,
+}
+
+fn children<'a>(node: &Node<'a>) -> Vec<Node<'a>> {
+    node.children(&mut node.walk()).collect()
+}
+
+/// Pairs of indices of the longest common subsequence
+fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    // Length of the LCS of the suffixes
+    let mut table = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+struct Differ<'a> {
+    passing: &'a Original,
+    failing: &'a Original,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn diff(&mut self, a: Node<'a>, b: Node<'a>) {
+        let b_text = &self.failing.text[b.byte_range()];
+        if self.passing.text[a.byte_range()] == *b_text {
+            return;
+        }
+        if a.kind_id() != b.kind_id() || a.child_count() == 0 || b.child_count() == 0 {
+            self.changes.push(Change {
+                range: a.byte_range(),
+                text: b_text.to_vec(),
+            });
+            return;
+        }
+        let a_children = children(&a);
+        let b_children = children(&b);
+        let key = |orig: &'a Original, n: &Node<'a>| (n.kind_id(), &orig.text[n.byte_range()]);
+        let a_keys: Vec<_> = a_children.iter().map(|n| key(self.passing, n)).collect();
+        let b_keys: Vec<_> = b_children.iter().map(|n| key(self.failing, n)).collect();
+        let (mut i, mut j) = (0, 0);
+        for (m, n) in lcs(&a_keys, &b_keys)
+            .into_iter()
+            .chain(std::iter::once((a_children.len(), b_children.len())))
+        {
+            self.gap((a, &a_children, i..m), (b, &b_children, j..n));
+            i = m + 1;
+            j = n + 1;
+        }
+    }
+
+    /// Diff children that weren't aligned, by aligning them by kind
+    fn gap(
+        &mut self,
+        (a, a_children, a_gap): (Node<'a>, &[Node<'a>], Range<usize>),
+        (b, b_children, b_gap): (Node<'a>, &[Node<'a>], Range<usize>),
+    ) {
+        if a_gap.is_empty() && b_gap.is_empty() {
+            return;
+        }
+        let kinds =
+            |children: &[Node<'a>]| -> Vec<u16> { children.iter().map(Node::kind_id).collect() };
+        let pairs = lcs(
+            &kinds(&a_children[a_gap.clone()]),
+            &kinds(&b_children[b_gap.clone()]),
+        );
+        // Everything between the surrounding aligned children
+        let between = |node: Node<'a>, children: &[Node<'a>], range: Range<usize>| {
+            let start = match range.start {
+                0 => node.start_byte(),
+                s => children[s - 1].end_byte(),
+            };
+            let end = children
+                .get(range.end)
+                .map_or(node.end_byte(), Node::start_byte);
+            start..end
+        };
+        let (mut i, mut j) = (a_gap.start, b_gap.start);
+        for (m, n) in pairs
+            .into_iter()
+            .map(|(m, n)| (a_gap.start + m, b_gap.start + n))
+            .chain(std::iter::once((a_gap.end, b_gap.end)))
+        {
+            if i < m || j < n {
+                self.changes.push(Change {
+                    range: between(a, a_children, i..m),
+                    text: self.failing.text[between(b, b_children, j..n)].to_vec(),
+                });
+            }
+            if m < a_gap.end {
+                self.diff(a_children[m], b_children[n]);
+            }
+            i = m + 1;
+            j = n + 1;
+        }
+    }
+}
+
+/// The changes that turn the passing version into the failing one (up to
+/// whitespace between nodes), in order
+pub(super) fn changes(passing: &Original, failing: &Original) -> Vec<Change> {
+    let mut differ = Differ {
+        passing,
+        failing,
+        changes: Vec::new(),
+    };
+    differ.diff(passing.tree.root_node(), failing.tree.root_node());
+    differ.changes
+}
+
+/// The passing version, with the changes that are kept
+fn apply(passing: &[u8], changes: &[Change], keep: &[bool]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(passing.len());
+    let mut pos = 0;
+    for (change, _) in changes.iter().zip(keep).filter(|(_, k)| **k) {
+        out.extend_from_slice(&passing[pos..change.range.start]);
+        out.extend_from_slice(&change.text);
+        pos = change.range.end;
+    }
+    out.extend_from_slice(&passing[pos..]);
+    out
+}
+
+/// Find a minimal set of changes from the passing version to the failing one
+/// that's still interesting, and apply them to the passing version.
+///
+/// # Errors
+///
+/// If the check fails, or if applying all of the changes isn't interesting
+/// (e.g., because the interestingness depends on whitespace).
+pub fn reduce_diff<T: Check>(
+    passing: &Original,
+    failing: &Original,
+    check: &T,
+) -> Result<(Vec<u8>, Vec<Change>), ReductionError> {
+    if check.interesting(&passing.text)? {
+        warn!("The passing version is interesting too, there's nothing to do");
+        return Ok((passing.text.clone(), Vec::new()));
+    }
+    let changes = changes(passing, failing);
+    info!("Delta debugging over {} changes", changes.len());
+    let all = vec![true; changes.len()];
+    if !check.interesting(&apply(&passing.text, &changes, &all))? {
+        return Err(ReductionError::Diff);
+    }
+    let keep = ddmin(changes.len(), |keep| {
+        Ok(check.interesting(&apply(&passing.text, &changes, keep))?)
+    })?;
+    let text = apply(&passing.text, &changes, &keep);
+    let kept = changes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(c, k)| k.then_some(c))
+        .collect();
+    Ok((text, kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let mut parse = |src: &str| Original::new(parser.parse(src, None).unwrap(), src.into());
+        let passing = parse("int x = 1;\nint y;\nint f(void) { return x; }\n");
+        let failing = parse("int x = 2;\nint f(void) { return x / 0; }\nint z;\n");
+        let changes = changes(&passing, &failing);
+        assert_eq!(changes.len(), 4);
+        let all = vec![true; changes.len()];
+        assert_eq!(
+            apply(&passing.text, &changes, &all),
+            failing.text.as_slice()
+        );
+        let texts: Vec<_> = changes
+            .iter()
+            .map(|c| String::from_utf8_lossy(&c.text).into_owned())
+            .collect();
+        assert_eq!(texts[0], "2");
+        assert!(texts.iter().any(|t| t.trim() == "x / 0"));
+    }
+}
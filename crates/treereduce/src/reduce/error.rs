@@ -3,6 +3,8 @@ use std::sync::PoisonError;
 
 use thiserror::Error;
 
+use crate::rewrite::RewriteError;
+
 #[derive(Debug, Error)]
 pub enum ReductionError {
     #[error("I/O error")]
@@ -11,6 +13,12 @@ pub enum ReductionError {
     Json(#[from] serde_json::Error),
     #[error("Lock poisoned")]
     LockError(String),
+    #[error("Bad query")]
+    Query(#[from] tree_sitter::QueryError),
+    #[error("Rewrite rule error")]
+    Rewrite(#[from] RewriteError),
+    #[error("Applying all of the changes from the passing version isn't interesting")]
+    Diff,
 }
 
 impl<T> From<PoisonError<T>> for ReductionError {
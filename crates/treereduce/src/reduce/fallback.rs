@@ -0,0 +1,66 @@
+//! Delta debugging over lines and tokens, for parts of the input that didn't
+//! parse.
+//
+// The main passes work on the parse tree, so they can't do much with `ERROR`
+// nodes: they can only delete or keep them whole. This pass falls back to
+// grammar-agnostic delta debugging inside of them (or over the whole file, if
+// most of it didn't parse).
+
+use std::ops::Range;
+
+use crate::check::Check;
+use crate::original::Original;
+
+use super::ddmin::{self, Granularity};
+use super::error::ReductionError;
+
+/// The top-most `ERROR` nodes, or the whole file if they cover most of it
+fn regions(orig: &Original) -> Vec<Range<usize>> {
+    let root = orig.tree.root_node();
+    if !root.has_error() {
+        return Vec::new();
+    }
+    let mut errors = Vec::new();
+    let mut queue = vec![root];
+    while let Some(node) = queue.pop() {
+        if node.is_error() {
+            errors.push(node.byte_range());
+        } else if node.has_error() {
+            queue.extend(node.children(&mut orig.tree.walk()));
+        }
+    }
+    let covered: usize = errors.iter().map(ExactSizeIterator::len).sum();
+    if root.is_error() || 2 * covered > orig.text.len() {
+        return std::iter::once(0..orig.text.len()).collect();
+    }
+    errors.sort_by_key(|r| r.start);
+    errors
+}
+
+/// Delete lines or tokens inside of parse errors.
+pub(super) fn reduce_errors<T: Check>(
+    orig: &Original,
+    check: &T,
+    granularity: Granularity,
+) -> Result<Vec<u8>, ReductionError> {
+    ddmin::reduce(orig, check, granularity, &regions(orig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int x;\nint y;\nint z;\nint w;\n@ $ ;\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let regions = regions(&orig);
+        assert_eq!(regions.len(), 1);
+        assert!(src[regions[0].clone()].contains('@'));
+        assert!(!src[regions[0].clone()].contains("int"));
+    }
+}
@@ -0,0 +1,48 @@
+//! Reduce the arguments of the check command, e.g., compiler flags.
+
+use tracing::info;
+
+use crate::check::{Check, CmdCheck};
+
+use super::ddmin::ddmin;
+use super::error::ReductionError;
+
+/// Delete as many of the check's reducible arguments (see
+/// [`CmdCheck::with_reducible_args`]) as possible while the files are still
+/// interesting.
+///
+/// # Errors
+///
+/// If the check fails.
+pub fn reduce_flags(check: &CmdCheck, files: &[&[u8]]) -> Result<CmdCheck, ReductionError> {
+    let count = check.reducible_args().len();
+    if count == 0 {
+        return Ok(check.clone());
+    }
+    info!("Delta debugging over {count} flags");
+    let keep = ddmin(count, |keep| {
+        Ok(check.keeping_args(keep).interesting_files(files)?)
+    })?;
+    Ok(check.keeping_args(&keep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce_flags() {
+        let args = [
+            "-c",
+            r#"case "$*" in *-O2*) exit 0;; *) exit 1;; esac"#,
+            "sh",
+        ];
+        let flags = ["-a", "-O2", "-b", "-c"];
+        let all: Vec<&str> = args.iter().chain(&flags).copied().collect();
+        let check = CmdCheck::exit_zero("sh", &all)
+            .with_reducible_args(args.len()..args.len() + flags.len());
+        let reduced = reduce_flags(&check, &[b""]).unwrap();
+        assert_eq!(reduced.reducible_args(), ["-O2"]);
+        assert_eq!(reduced.args.len(), args.len() + 1);
+    }
+}
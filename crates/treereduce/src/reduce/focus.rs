@@ -0,0 +1,89 @@
+//! Restrict reduction to part of the program.
+//
+// Focusing is the opposite of protection: everything outside the focused
+// region is kept verbatim, as if it had been passed as a protected range (and
+// it's carried from pass to pass like one). To reduce the rest last, the
+// pipeline runs again once the focused region is done, without those ranges.
+
+use std::ops::Range;
+
+use tracing::warn;
+use tree_sitter::QueryError;
+
+use crate::original::Original;
+
+use super::keep;
+
+/// Name of the capture for the nodes to focus on
+const FOCUS: &str = "focus";
+
+/// Where to reduce, if not the whole program
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Focus {
+    /// Tree-sitter queries, the nodes captured as `@focus` (or matched, if
+    /// there's no such capture) are reduced
+    pub queries: Vec<String>,
+    /// Byte ranges to reduce
+    pub ranges: Vec<Range<usize>>,
+    /// Then reduce the rest of the program, too
+    pub then_rest: bool,
+}
+
+impl Focus {
+    pub(super) fn is_empty(&self) -> bool {
+        self.queries.is_empty() && self.ranges.is_empty()
+    }
+
+    /// Byte ranges of the text outside of the focus, empty if there's no
+    /// focus
+    pub(super) fn outside(&self, orig: &Original) -> Result<Vec<Range<usize>>, QueryError> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut inside = self.ranges.clone();
+        for source in &self.queries {
+            inside.extend(keep::captured(orig, source, FOCUS)?);
+        }
+        if inside.is_empty() {
+            warn!("Nothing to focus on, the input won't be reduced");
+        }
+        inside.sort_by_key(|r| r.start);
+        let mut outside = Vec::new();
+        let mut start = 0;
+        for range in inside {
+            if start < range.start {
+                outside.push(start..range.start);
+            }
+            start = start.max(range.end);
+        }
+        if start < orig.text.len() {
+            outside.push(start..orig.text.len());
+        }
+        Ok(outside)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outside() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int f(void) { return 1; }\nint g(void) { return 2; }\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let focus = Focus {
+            queries: vec![
+                "(function_definition declarator: (_ declarator: (identifier) @name) (#eq? @name \"g\")) @focus"
+                    .to_string(),
+            ],
+            ranges: vec![4..5, 0..2],
+            then_rest: false,
+        };
+        assert_eq!(focus.outside(&orig).unwrap(), vec![2..4, 5..26, 51..52]);
+        assert!(Focus::default().outside(&orig).unwrap().is_empty());
+    }
+}
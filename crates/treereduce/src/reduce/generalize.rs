@@ -0,0 +1,272 @@
+//! Find the parts of a (reduced) program that don't matter.
+//
+// A node is a wildcard if replacing it with each of a few alternatives keeps
+// the program interesting. Identifiers are replaced consistently (all
+// occurrences at once), literals with other values of the same type, and other
+// nodes with the text of other nodes of the same kind. Larger nodes are tried
+// first, and nothing inside a wildcard is tried.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+use tracing::{debug, info};
+use tree_sitter::Node;
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+use super::{literal, rename, Config};
+
+/// How many alternatives to try for each node
+const MAX_ALTERNATIVES: usize = 2;
+
+const OPEN: &str = "«";
+const CLOSE: &str = "»";
+
+/// A node (or each occurrence of an identifier) that can be replaced without
+/// making the program uninteresting
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Wildcard {
+    pub kind: String,
+    /// Byte range of each occurrence
+    pub ranges: Vec<Range<usize>>,
+    /// How many alternatives were tried
+    pub alternatives: usize,
+}
+
+impl Wildcard {
+    pub fn write_text(&self, text: &[u8], w: &mut impl Write) -> io::Result<()> {
+        let Some(first) = self.ranges.first() else {
+            return Ok(());
+        };
+        let before = &text[..first.start];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let column = first.start
+            - before
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |i| i + 1)
+            + 1;
+        write!(
+            w,
+            "{line}:{column} {} `{}` (tried {} alternative{}",
+            self.kind,
+            String::from_utf8_lossy(&text[first.clone()]),
+            self.alternatives,
+            if self.alternatives == 1 { "" } else { "s" }
+        )?;
+        if self.ranges.len() > 1 {
+            write!(w, ", {} occurrences", self.ranges.len())?;
+        }
+        writeln!(w, ")")
+    }
+}
+
+/// Surround each occurrence of each wildcard with `«` and `»`.
+#[must_use]
+pub fn annotate(text: &[u8], wildcards: &[Wildcard]) -> Vec<u8> {
+    let mut ranges: Vec<&Range<usize>> = wildcards.iter().flat_map(|w| &w.ranges).collect();
+    ranges.sort_by_key(|r| r.start);
+    let mut out = Vec::with_capacity(text.len());
+    let mut pos = 0;
+    for range in ranges {
+        out.extend_from_slice(&text[pos..range.start]);
+        out.extend_from_slice(OPEN.as_bytes());
+        out.extend_from_slice(&text[range.clone()]);
+        out.extend_from_slice(CLOSE.as_bytes());
+        pos = range.end;
+    }
+    out.extend_from_slice(&text[pos..]);
+    out
+}
+
+struct Generalizer<'a, T> {
+    orig: &'a Original,
+    check: &'a T,
+    nodes: HashMap<NodeId, Node<'a>>,
+    wildcards: Vec<Wildcard>,
+}
+
+impl<T: Check> Generalizer<'_, T> {
+    fn inside_wildcard(&self, node: &Node<'_>) -> bool {
+        self.wildcards
+            .iter()
+            .flat_map(|w| &w.ranges)
+            .any(|r| r.start <= node.start_byte() && node.end_byte() <= r.end)
+    }
+
+    /// Whether replacing all of the nodes with each alternative is interesting
+    fn all_interesting(
+        &self,
+        node_ids: &[NodeId],
+        alternatives: &[String],
+    ) -> Result<bool, ReductionError> {
+        for alternative in alternatives {
+            let edits = node_ids.iter().fold(Edits::new(), |edits, id| {
+                edits.replace_id(*id, alternative.clone())
+            });
+            let mut text = Vec::with_capacity(self.orig.text.len());
+            render(&mut text, &self.orig.tree, &self.orig.text, &edits)?;
+            if !self.check.interesting(&text)? {
+                return Ok(false);
+            }
+        }
+        Ok(!alternatives.is_empty())
+    }
+
+    fn try_nodes(
+        &mut self,
+        node_ids: &[NodeId],
+        alternatives: &[String],
+    ) -> Result<(), ReductionError> {
+        let nodes: Vec<Node<'_>> = node_ids
+            .iter()
+            .filter_map(|id| self.nodes.get(id).copied())
+            .filter(|n| !self.inside_wildcard(n))
+            .collect();
+        let Some(first) = nodes.first() else {
+            return Ok(());
+        };
+        let ids: Vec<NodeId> = nodes.iter().map(NodeId::new).collect();
+        if self.all_interesting(&ids, alternatives)? {
+            debug!(
+                "{} at byte {} is a wildcard",
+                first.kind(),
+                first.start_byte()
+            );
+            self.wildcards.push(Wildcard {
+                kind: first.kind().to_string(),
+                ranges: nodes.iter().map(Node::byte_range).collect(),
+                alternatives: alternatives.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Texts of other nodes of the same kind, and replacements for the kind
+fn alternatives<T>(
+    orig: &Original,
+    node: &Node<'_>,
+    by_kind: &HashMap<&str, Vec<Node<'_>>>,
+    conf: &Config<T>,
+) -> Vec<String> {
+    let text = &orig.text[node.byte_range()];
+    let mut alternatives = BTreeSet::new();
+    let kind = node.kind();
+    for other in by_kind.get(kind).into_iter().flatten() {
+        if other.end_byte() <= node.start_byte() || node.end_byte() <= other.start_byte() {
+            alternatives.insert(orig.text[other.byte_range()].to_vec());
+        }
+    }
+    if let Some(rs) = conf.replacements.get(kind) {
+        alternatives.extend(rs.iter().map(|r| r.as_bytes().to_vec()));
+    } else if let Some(r) = conf.default_replacements.get(kind) {
+        alternatives.insert(r.as_bytes().to_vec());
+    }
+    alternatives.remove(text);
+    let mut alternatives: Vec<String> = alternatives
+        .into_iter()
+        .filter_map(|a| String::from_utf8(a).ok())
+        .collect();
+    alternatives.sort_by_key(String::len);
+    alternatives.truncate(MAX_ALTERNATIVES);
+    alternatives
+}
+
+/// Find the nodes that can be replaced without making the program
+/// uninteresting, in order of position.
+///
+/// # Errors
+///
+/// If the interestingness check fails to run.
+pub fn generalize<T: Check>(
+    orig: &Original,
+    conf: &Config<T>,
+) -> Result<Vec<Wildcard>, ReductionError> {
+    let literals = literal::alternatives(orig, MAX_ALTERNATIVES);
+    let identifiers = rename::alternatives(orig, MAX_ALTERNATIVES);
+
+    let mut nodes = HashMap::new();
+    let mut by_kind: HashMap<&str, Vec<Node<'_>>> = HashMap::new();
+    let mut preorder = Vec::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let children: Vec<_> = node.children(&mut orig.tree.walk()).collect();
+        stack.extend(children.into_iter().rev());
+        nodes.insert(NodeId::new(&node), node);
+        if node.is_named() && !node.is_extra() && !node.has_error() && node.parent().is_some() {
+            by_kind.entry(node.kind()).or_default().push(node);
+            preorder.push(node);
+        }
+    }
+
+    let mut generalizer = Generalizer {
+        orig,
+        check: &conf.check,
+        nodes,
+        wildcards: Vec::new(),
+    };
+    let special: Vec<NodeId> = literals
+        .iter()
+        .map(|(node, _)| NodeId::new(node))
+        .chain(identifiers.iter().flat_map(|(ids, _)| ids.iter().copied()))
+        .collect();
+    for node in preorder {
+        let id = NodeId::new(&node);
+        if special.contains(&id) {
+            continue;
+        }
+        let alternatives = alternatives(orig, &node, &by_kind, conf);
+        generalizer.try_nodes(&[id], &alternatives)?;
+    }
+    for (ids, alternatives) in identifiers {
+        generalizer.try_nodes(&ids, &alternatives)?;
+    }
+    for (node, alternatives) in literals {
+        generalizer.try_nodes(&[NodeId::new(&node)], &alternatives)?;
+    }
+
+    let mut wildcards = generalizer.wildcards;
+    wildcards.sort_by_key(|w| w.ranges.first().map(|r| r.start));
+    info!("Found {} wildcards", wildcards.len());
+    Ok(wildcards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotate() {
+        let text = b"int main() { return f(x, 5); }";
+        let wildcards = vec![
+            Wildcard {
+                kind: "number_literal".to_string(),
+                ranges: std::iter::once(25..26).collect(),
+                alternatives: 2,
+            },
+            Wildcard {
+                kind: "identifier".to_string(),
+                ranges: std::iter::once(22..23).collect(),
+                alternatives: 2,
+            },
+        ];
+        assert_eq!(
+            String::from_utf8(annotate(text, &wildcards)).unwrap(),
+            "int main() { return f(«x», «5»); }"
+        );
+        let mut report = Vec::new();
+        wildcards[1].write_text(text, &mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "1:23 identifier `x` (tried 2 alternatives)\n"
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Inline functions (and function-like macros) that are called exactly once.
+//
+// Which nodes are definitions and calls is up to per-language queries. The
+// inlined text is the function's body, or the expression it returns if it
+// consists of a single `return`, with each parameter replaced by the
+// (parenthesized) corresponding argument. This is frequently wrong (e.g., if
+// the body has early returns, or an argument has side effects), but the
+// interestingness test sorts that out.
+
+use std::collections::HashMap;
+
+use tree_sitter::{Node, Parser, Query, QueryCursor, QueryError, StreamingIterator};
+
+use crate::id::NodeId;
+use crate::original::Original;
+
+/// Per-language queries for finding function definitions and calls.
+///
+/// The definition query must capture `@definition`, `@name`, `@params`, and
+/// `@body`. The call query must capture `@call`, `@name`, and `@args`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InlineQueries {
+    pub definition: &'static str,
+    pub call: &'static str,
+}
+
+impl InlineQueries {
+    /// C (including function-like macros)
+    pub const C: InlineQueries = InlineQueries {
+        definition: "[(function_definition
+             declarator: (function_declarator
+               declarator: (identifier) @name
+               parameters: (parameter_list) @params)
+             body: (compound_statement) @body)
+           (preproc_function_def
+             name: (identifier) @name
+             parameters: (preproc_params) @params
+             value: (preproc_arg) @body)] @definition",
+        call: "(call_expression
+             function: (identifier) @name
+             arguments: (argument_list) @args) @call",
+    };
+
+    /// Java
+    pub const JAVA: InlineQueries = InlineQueries {
+        definition: "(method_declaration
+             name: (identifier) @name
+             parameters: (formal_parameters) @params
+             body: (block) @body) @definition",
+        call: "(method_invocation
+             !object
+             name: (identifier) @name
+             arguments: (argument_list) @args) @call",
+    };
+
+    /// JavaScript
+    pub const JAVASCRIPT: InlineQueries = InlineQueries {
+        definition: "(function_declaration
+             name: (identifier) @name
+             parameters: (formal_parameters) @params
+             body: (statement_block) @body) @definition",
+        call: "(call_expression
+             function: (identifier) @name
+             arguments: (arguments) @args) @call",
+    };
+
+    /// Rust
+    pub const RUST: InlineQueries = InlineQueries {
+        definition: "(function_item
+             name: (identifier) @name
+             parameters: (parameters) @params
+             body: (block) @body) @definition",
+        call: "(call_expression
+             function: (identifier) @name
+             arguments: (arguments) @args) @call",
+    };
+}
+
+/// Fields that hold the name in a parameter
+const PARAM_NAME_FIELDS: &[&str] = &["declarator", "pattern", "name"];
+
+#[derive(Debug)]
+pub(super) struct Inlining {
+    /// The call, or the statement that's just the call
+    pub(super) call: NodeId,
+    pub(super) definition: NodeId,
+    pub(super) with: String,
+}
+
+fn elements<'tree>(list: &Node<'tree>) -> Vec<Node<'tree>> {
+    list.named_children(&mut list.walk())
+        .filter(|n| !n.is_extra())
+        .collect()
+}
+
+fn text<'a>(orig: &'a Original, node: &Node<'_>) -> &'a str {
+    std::str::from_utf8(&orig.text[node.byte_range()]).unwrap_or_default()
+}
+
+/// The identifier that a parameter binds, e.g., `x` in `int *x`
+fn param_name<'a>(orig: &'a Original, param: &Node<'_>) -> Option<&'a str> {
+    let mut node = *param;
+    loop {
+        if node.kind().contains("identifier") {
+            return Some(text(orig, &node));
+        }
+        node = PARAM_NAME_FIELDS
+            .iter()
+            .find_map(|field| node.child_by_field_name(field))?;
+    }
+}
+
+/// If the body is just `return e;`, then `e`
+fn returned<'tree>(body: &Node<'tree>) -> Option<Node<'tree>> {
+    match elements(body).as_slice() {
+        [ret] if ret.kind().contains("return") => match elements(ret).as_slice() {
+            [e] => Some(*e),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the node is the name of a field, e.g., `x` in `s.x`
+fn is_field(node: &Node<'_>) -> bool {
+    node.parent().is_some_and(|p| {
+        ["field", "property"]
+            .iter()
+            .any(|f| p.child_by_field_name(f).as_ref() == Some(node))
+    })
+}
+
+/// The text of `node`, with each identifier that names a parameter replaced
+/// by the (parenthesized) corresponding argument.
+fn substitute(node: &Node<'_>, text: &[u8], bindings: &HashMap<&str, &str>) -> String {
+    let mut out = Vec::with_capacity(node.byte_range().len());
+    let mut pos = node.start_byte();
+    let mut stack = vec![*node];
+    while let Some(n) = stack.pop() {
+        if n.child_count() != 0 {
+            let children: Vec<_> = n.children(&mut n.walk()).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        if n.kind() != "identifier" || is_field(&n) {
+            continue;
+        }
+        let word = std::str::from_utf8(&text[n.byte_range()]).unwrap_or_default();
+        if let Some(arg) = bindings.get(word) {
+            out.extend_from_slice(&text[pos..n.start_byte()]);
+            out.push(b'(');
+            out.extend_from_slice(arg.as_bytes());
+            out.push(b')');
+            pos = n.end_byte();
+        }
+    }
+    out.extend_from_slice(&text[pos..node.end_byte()]);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The body of a macro isn't parsed along with the rest of the program, so
+/// parse it on its own.
+fn substitute_macro(orig: &Original, body: &str, bindings: &HashMap<&str, &str>) -> Option<String> {
+    let mut parser = Parser::new();
+    parser.set_language(&orig.tree.language()).ok()?;
+    let tree = parser.parse(body, None)?;
+    Some(substitute(&tree.root_node(), body.as_bytes(), bindings))
+}
+
+struct Definition<'tree> {
+    node: Node<'tree>,
+    params: Node<'tree>,
+    body: Node<'tree>,
+}
+
+struct Call<'tree> {
+    node: Node<'tree>,
+    args: Node<'tree>,
+}
+
+fn capture<'tree>(
+    query: &Query,
+    m: &tree_sitter::QueryMatch<'_, 'tree>,
+    name: &str,
+) -> Option<Node<'tree>> {
+    let index = query.capture_index_for_name(name)?;
+    m.nodes_for_capture_index(index).next()
+}
+
+/// Find functions that are called exactly once, and what to replace the
+/// call with.
+pub(super) fn inlinings(
+    orig: &Original,
+    queries: &InlineQueries,
+) -> Result<Vec<Inlining>, QueryError> {
+    let language = orig.tree.language();
+    let def_query = Query::new(&language, queries.definition)?;
+    let call_query = Query::new(&language, queries.call)?;
+    let root = orig.tree.root_node();
+    let mut cursor = QueryCursor::new();
+
+    let mut definitions: HashMap<&str, Vec<Definition<'_>>> = HashMap::new();
+    let mut matches = cursor.matches(&def_query, root, orig.text.as_slice());
+    while let Some(m) = matches.next() {
+        let get = |name| capture(&def_query, m, name);
+        if let (Some(node), Some(name), Some(params), Some(body)) =
+            (get("definition"), get("name"), get("params"), get("body"))
+        {
+            definitions
+                .entry(text(orig, &name))
+                .or_default()
+                .push(Definition { node, params, body });
+        }
+    }
+
+    let mut calls: HashMap<&str, Vec<Call<'_>>> = HashMap::new();
+    let mut matches = cursor.matches(&call_query, root, orig.text.as_slice());
+    while let Some(m) = matches.next() {
+        let get = |name| capture(&call_query, m, name);
+        if let (Some(node), Some(name), Some(args)) = (get("call"), get("name"), get("args")) {
+            calls
+                .entry(text(orig, &name))
+                .or_default()
+                .push(Call { node, args });
+        }
+    }
+
+    let mut inlinings = Vec::new();
+    for (name, defs) in &definitions {
+        let ([def], Some([call])) = (defs.as_slice(), calls.get(name).map(Vec::as_slice)) else {
+            continue;
+        };
+        // Recursive
+        if def.node.byte_range().contains(&call.node.start_byte()) {
+            continue;
+        }
+        let params = elements(&def.params);
+        let args = elements(&call.args);
+        if params.len() != args.len() {
+            continue;
+        }
+        let Some(names) = params
+            .iter()
+            .map(|p| param_name(orig, p))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let bindings: HashMap<&str, &str> = names
+            .into_iter()
+            .zip(args.iter().map(|a| text(orig, a)))
+            .collect();
+        let mut call_node = call.node;
+        let with = match returned(&def.body) {
+            Some(e) => format!("({})", substitute(&e, &orig.text, &bindings)),
+            None if def.body.child_count() == 0 => {
+                // A macro
+                let body = text(orig, &def.body).trim();
+                let Some(body) = substitute_macro(orig, body, &bindings) else {
+                    continue;
+                };
+                format!("({body})")
+            }
+            None => {
+                // A block can only stand in for a statement
+                match call.node.parent() {
+                    Some(p) if p.kind() == "expression_statement" => call_node = p,
+                    _ => continue,
+                }
+                substitute(&def.body, &orig.text, &bindings)
+            }
+        };
+        inlinings.push(Inlining {
+            call: NodeId::new(&call_node),
+            definition: NodeId::new(&def.node),
+            with,
+        });
+    }
+    Ok(inlinings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edits::Edits;
+
+    fn inline(src: &str) -> String {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let mut edits = Edits::new();
+        for i in inlinings(&orig, &InlineQueries::C).unwrap() {
+            edits = edits.omit_id(i.definition).replace_id(i.call, i.with);
+        }
+        let mut out = Vec::new();
+        tree_sitter_edit::render(&mut out, &orig.tree, src.as_bytes(), &edits).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            inline("int sq(int *x) { return *x * *x; }\nint main() { return sq(&y); }"),
+            "\nint main() { return (*(&y) * *(&y)); }"
+        );
+        assert_eq!(
+            inline("#define SQ(x) x*x\nint main() { return SQ(2); }"),
+            "int main() { return ((2)*(2)); }"
+        );
+        // Not in strings or field names
+        assert_eq!(
+            inline("int f(struct s x) { return g(\"x\", x.x); }\nint main() { f(y); }"),
+            "\nint main() { (g(\"x\", (y).x)); }"
+        );
+        assert_eq!(
+            inline("#define F(x) g(\"x\", x.x)\nint main() { F(y); }"),
+            "int main() { (g(\"x\", (y).x)); }"
+        );
+        assert_eq!(
+            inline("void f(int x) { g(x); }\nint main() { f(1); }"),
+            "\nint main() { { g((1)); } }"
+        );
+        let in_expression = "void f(int x) { g(x); }\nint main() { int r = (f(1), 0); }";
+        assert_eq!(inline(in_expression), in_expression);
+        // Called twice
+        let twice = "int f(int x) { return x; }\nint main() { return f(1) + f(2); }";
+        assert_eq!(inline(twice), twice);
+    }
+}
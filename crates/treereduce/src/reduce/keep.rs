@@ -0,0 +1,606 @@
+//! Protect parts of the program from reduction.
+//
+// Protected nodes are never deleted or replaced, so neither are their
+// ancestors. Unless `reduce_inside` is set, their descendants aren't either;
+// such "frozen" nodes are kept verbatim. Otherwise, just the first and last
+// tokens of a protected node are frozen, which is enough to keep the node
+// itself (and its ancestors) while anything in between may be reduced.
+//
+// The tree passes consult the protected nodes directly. The other passes don't
+// know about nodes, so their checks are wrapped in a `Guard` that rejects any
+// candidate that changes the text of a frozen range. The guard compares each
+// candidate with the last interesting text, and keeps track of where the
+// frozen ranges are as the text shrinks.
+//
+// The frozen ranges are found once, before reduction starts, and carried from
+// pass to pass through the edits each one applies (or, for passes that produce
+// text, by the guard). Finding them again would go wrong once a query stops
+// matching a node whose insides were reduced, or its text also appears
+// elsewhere.
+
+use std::collections::HashSet;
+use std::io;
+use std::iter;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use tracing::warn;
+use tree_sitter::{Node, Query, QueryCursor, QueryError, StreamingIterator};
+use tree_sitter_edit::Edit;
+
+use crate::check::Check;
+use crate::id::NodeId;
+use crate::original::Original;
+
+/// Name of the capture for the nodes to protect
+const KEEP: &str = "keep";
+
+/// What to protect from reduction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keep {
+    /// Tree-sitter queries, the nodes captured as `@keep` (or matched, if
+    /// there's no such capture) are protected
+    pub queries: Vec<String>,
+    /// Node kinds to protect
+    pub kinds: Vec<String>,
+    /// Byte ranges of the text to keep verbatim
+    pub ranges: Vec<Range<usize>>,
+    /// Protect the node after each comment that contains this text, e.g.,
+    /// `KEEP`
+    pub marker: Option<String>,
+    /// Still reduce the descendants of nodes protected by query, kind, or
+    /// marker, other than their first and last tokens
+    pub reduce_inside: bool,
+}
+
+impl Keep {
+    /// The byte ranges to keep verbatim in the file at `index` of several;
+    /// [`Keep::ranges`] only refer to the first one.
+    pub(super) fn resolve(
+        &self,
+        orig: &Original,
+        index: usize,
+    ) -> Result<Vec<Range<usize>>, QueryError> {
+        let mut frozen = Vec::new();
+        if index == 0 {
+            frozen.extend(self.ranges.iter().cloned());
+        }
+        let protect = |node: Node<'_>| {
+            if self.reduce_inside {
+                ends(node)
+            } else {
+                vec![node.byte_range()]
+            }
+        };
+
+        let root = orig.tree.root_node();
+        for source in &self.queries {
+            for range in captured(orig, source, KEEP)? {
+                if let Some(node) = root.descendant_for_byte_range(range.start, range.end) {
+                    frozen.extend(protect(node));
+                }
+            }
+        }
+
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children(&mut orig.tree.walk()));
+            if self.kinds.iter().any(|k| k == node.kind()) {
+                frozen.extend(protect(node));
+            }
+            if let Some(marker) = &self.marker {
+                let text = &orig.text[node.byte_range()];
+                if is_comment(&node) && String::from_utf8_lossy(text).contains(marker.as_str()) {
+                    if let Some(marked) = marked(&node) {
+                        frozen.extend(protect(marked));
+                    }
+                    frozen.push(node.byte_range());
+                }
+            }
+        }
+        Ok(frozen)
+    }
+}
+
+/// Byte ranges of the nodes captured as `@capture`, or matched by the whole
+/// query if it has no such capture
+pub(super) fn captured(
+    orig: &Original,
+    source: &str,
+    capture: &str,
+) -> Result<Vec<Range<usize>>, QueryError> {
+    let root_capture = format!("@{capture}");
+    let source = if source.contains(&root_capture) {
+        source.to_string()
+    } else {
+        format!("{source} {root_capture}")
+    };
+    let query = Query::new(&orig.tree.language(), &source)?;
+    let mut ranges = Vec::new();
+    let Some(index) = query.capture_index_for_name(capture) else {
+        return Ok(ranges);
+    };
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, orig.tree.root_node(), orig.text.as_slice());
+    while let Some(m) = matches.next() {
+        ranges.extend(m.nodes_for_capture_index(index).map(|n| n.byte_range()));
+    }
+    Ok(ranges)
+}
+
+fn overlaps(node: &Node<'_>, range: &Range<usize>) -> bool {
+    range.start < node.end_byte() && node.start_byte() < range.end
+}
+
+fn within(node: &Node<'_>, range: &Range<usize>) -> bool {
+    range.start <= node.start_byte() && node.end_byte() <= range.end
+}
+
+fn is_comment(node: &Node<'_>) -> bool {
+    node.is_extra() && node.kind().contains("comment")
+}
+
+/// The node that a marker comment refers to
+fn marked<'tree>(comment: &Node<'tree>) -> Option<Node<'tree>> {
+    let mut next = comment.next_named_sibling();
+    while let Some(n) = next {
+        if !n.is_extra() {
+            return Some(n);
+        }
+        next = n.next_named_sibling();
+    }
+    None
+}
+
+/// The byte ranges of the first and last tokens of a node
+fn ends(node: Node<'_>) -> Vec<Range<usize>> {
+    let (mut first, mut last) = (node, node);
+    while let Some(child) = first.child(0) {
+        first = child;
+    }
+    while let Some(child) = last.children(&mut last.walk()).last() {
+        last = child;
+    }
+    if first == last {
+        vec![first.byte_range()]
+    } else {
+        vec![first.byte_range(), last.byte_range()]
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Protection {
+    /// Nodes that can't be deleted or replaced, including the frozen ones
+    fixed: HashSet<NodeId>,
+    /// Nodes whose descendants can't be, either
+    frozen: HashSet<NodeId>,
+}
+
+impl Protection {
+    pub(super) fn new(orig: &Original, frozen: &[Range<usize>]) -> Self {
+        let mut protection = Protection::default();
+        if frozen.is_empty() {
+            return protection;
+        }
+        let mut stack = vec![orig.tree.root_node()];
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children(&mut orig.tree.walk()));
+            let id = NodeId::new(&node);
+            for range in frozen {
+                if !overlaps(&node, range) {
+                    continue;
+                }
+                if within(&node, range) {
+                    protection.frozen.insert(id);
+                }
+                protection.fixed.insert(id);
+            }
+        }
+        protection
+    }
+
+    /// Also protect the nodes of another file.
+    pub(super) fn extend(&mut self, other: Protection) {
+        self.fixed.extend(other.fixed);
+        self.frozen.extend(other.frozen);
+    }
+
+    pub(super) fn is_fixed(&self, id: NodeId) -> bool {
+        self.fixed.contains(&id)
+    }
+
+    pub(super) fn is_frozen(&self, id: NodeId) -> bool {
+        self.frozen.contains(&id)
+    }
+}
+
+/// Where the ranges are after the edits, which are in order. `None` if an edit
+/// overlaps one of them.
+pub(super) fn shift(ranges: &[Range<usize>], edits: &[Edit]) -> Option<Vec<Range<usize>>> {
+    ranges
+        .iter()
+        .map(|range| {
+            let (mut inserted, mut deleted) = (0, 0);
+            for edit in edits {
+                if edit.position + edit.delete <= range.start {
+                    inserted += edit.insert.len();
+                    deleted += edit.delete;
+                } else if edit.position < range.end {
+                    return None;
+                }
+            }
+            Some(range.start + inserted - deleted..range.end + inserted - deleted)
+        })
+        .collect()
+}
+
+/// The one edit that turns `old` into `new`, keeping as much of their common
+/// start (or, with `from_end`, their common end) as possible
+fn difference(old: &[u8], new: &[u8], from_end: bool) -> Edit {
+    let common = old.len().min(new.len());
+    let prefix = |limit: usize| {
+        old.iter()
+            .zip(new)
+            .take(limit)
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    let suffix = |limit: usize| {
+        old.iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(limit)
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    let (start, end) = if from_end {
+        let end = suffix(common);
+        (prefix(common - end), end)
+    } else {
+        let start = prefix(common);
+        (start, suffix(common - start))
+    };
+    Edit {
+        position: start,
+        delete: old.len() - start - end,
+        insert: new[start..new.len() - end].to_vec(),
+    }
+}
+
+/// Don't look for differences of more than this many inserted and deleted
+/// bytes, see [`diff`]
+const MAX_DIFF: usize = 256;
+
+/// The edits of a shortest edit script from `old` to `new`, if it inserts and
+/// deletes at most `max` bytes, found with Myers' algorithm. Unlike
+/// [`difference`], the edits may be on both sides of a protected range.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn diff(old: &[u8], new: &[u8], max: usize) -> Option<Vec<Edit>> {
+    let (n, m, max) = (old.len() as isize, new.len() as isize, max as isize);
+    // The furthest `x` reached on each diagonal `k = x - y`, at index
+    // `k + max + 1`. `None` if no path reaches it.
+    let index = |k: isize| (k + max + 1) as usize;
+    let mut v: Vec<Option<isize>> = vec![None; index(max + 2)];
+    v[index(1)] = Some(0);
+    // The furthest `x` on a diagonal with `d` inserted and deleted bytes,
+    // coming from the diagonal above (a deletion) or below (an insertion)
+    let step = |v: &[Option<isize>], d: isize, k: isize| -> Option<(isize, bool)> {
+        let valid = |x: &isize| *x <= n && x - k >= 0 && x - k <= m;
+        let right = (k > -d).then(|| v[index(k - 1)].map(|x| x + 1)).flatten();
+        // The path starts from a virtual point on the diagonal below
+        let down = (k < d || d == 0).then(|| v[index(k + 1)]).flatten();
+        match (right.filter(valid), down.filter(valid)) {
+            (Some(r), Some(x)) if r > x => Some((r, true)),
+            (_, Some(x)) => Some((x, false)),
+            (Some(r), None) => Some((r, true)),
+            (None, None) => None,
+        }
+    };
+    let mut trace = Vec::new();
+    let mut last = None;
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let Some((mut x, _)) = step(&trace[trace.len() - 1], d, k) else {
+                v[index(k)] = None;
+                continue;
+            };
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+            v[index(k)] = Some(x);
+            if x == n && x - k == m {
+                last = Some(d);
+                break 'search;
+            }
+        }
+    }
+
+    // Follow the path back, from the end to the start
+    let (mut x, mut y) = (n, m);
+    let mut ops = Vec::new();
+    for d in (1..=last?).rev() {
+        let k = x - y;
+        let (_, deletion) = step(&trace[d as usize], d, k)?;
+        let prev_k = if deletion { k - 1 } else { k + 1 };
+        let prev_x = trace[d as usize][index(prev_k)]?;
+        let prev_y = prev_x - prev_k;
+        ops.push(if deletion {
+            (prev_x as usize, None)
+        } else {
+            (prev_x as usize, Some(new[prev_y as usize]))
+        });
+        (x, y) = (prev_x, prev_y);
+    }
+
+    let mut edits: Vec<Edit> = Vec::new();
+    for (position, insert) in ops.into_iter().rev() {
+        let edit = match edits.last_mut() {
+            Some(edit) if edit.position + edit.delete == position => edit,
+            _ => {
+                edits.push(Edit {
+                    position,
+                    delete: 0,
+                    insert: Vec::new(),
+                });
+                edits.last_mut()?
+            }
+        };
+        match insert {
+            Some(byte) => edit.insert.push(byte),
+            None => edit.delete += 1,
+        }
+    }
+    Some(edits)
+}
+
+/// The last interesting text, and where the frozen ranges are in it
+#[derive(Debug)]
+pub(super) struct Tracked {
+    text: Vec<u8>,
+    frozen: Vec<Range<usize>>,
+}
+
+impl Tracked {
+    /// Where the ranges are in `text`, if the ways it differs from the tracked
+    /// text leave them alone. Changes in one place are cheap to find;
+    /// otherwise, fall back to a (bounded) diff.
+    fn follow(&self, text: &[u8]) -> Option<Tracked> {
+        [false, true]
+            .into_iter()
+            .map(|from_end| Some(vec![difference(&self.text, text, from_end)]))
+            .chain(iter::once_with(|| diff(&self.text, text, MAX_DIFF)))
+            .flatten()
+            .find_map(|edits| shift(&self.frozen, &edits))
+            .map(|frozen| Tracked {
+                text: text.to_vec(),
+                frozen,
+            })
+    }
+}
+
+/// A check that's only interesting if the text of the frozen ranges is intact
+pub(super) struct Guard<'a, T> {
+    check: &'a T,
+    /// `None` if nothing is frozen
+    tracked: Option<Mutex<Tracked>>,
+}
+
+impl<'a, T> Guard<'a, T> {
+    pub(super) fn new(orig: &Original, frozen: Vec<Range<usize>>, check: &'a T) -> Self {
+        let tracked = (!frozen.is_empty()).then(|| {
+            Mutex::new(Tracked {
+                text: orig.text.clone(),
+                frozen,
+            })
+        });
+        Guard { check, tracked }
+    }
+
+    /// Where the frozen ranges are in the result of the passes that used this
+    /// check, which is the last interesting text (or the original).
+    pub(super) fn frozen(&self, text: &[u8]) -> Vec<Range<usize>> {
+        let Some(tracked) = &self.tracked else {
+            return Vec::new();
+        };
+        let Ok(tracked) = tracked.lock() else {
+            return Vec::new();
+        };
+        tracked.follow(text).map_or_else(
+            || {
+                warn!("Lost track of the protected ranges");
+                Vec::new()
+            },
+            |t| t.frozen,
+        )
+    }
+
+    /// The candidate was interesting, so the next ones are compared with it.
+    fn accept(&self, candidate: Option<Tracked>) {
+        if let (Some(tracked), Some(candidate)) = (&self.tracked, candidate) {
+            if let Ok(mut tracked) = tracked.lock() {
+                *tracked = candidate;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum GuardState<S> {
+    /// The candidate changes a frozen range
+    Rejected,
+    Running {
+        state: S,
+        /// Where the ranges are in the candidate, if anything is frozen
+        candidate: Option<Tracked>,
+    },
+}
+
+impl<T: Check> Check for Guard<'_, T> {
+    type State = GuardState<T::State>;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        let candidate = match &self.tracked {
+            None => None,
+            Some(tracked) => match tracked.lock().ok().and_then(|t| t.follow(stdin)) {
+                Some(t) => Some(t),
+                None => return Ok(GuardState::Rejected),
+            },
+        };
+        Ok(GuardState::Running {
+            state: self.check.start(stdin)?,
+            candidate,
+        })
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        match state {
+            GuardState::Rejected => Ok(()),
+            GuardState::Running { state, .. } => self.check.cancel(state),
+        }
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        match state {
+            GuardState::Rejected => Ok(Some(false)),
+            GuardState::Running { state, candidate } => {
+                let verdict = self.check.try_wait(state)?;
+                if verdict == Some(true) {
+                    self.accept(candidate.take());
+                }
+                Ok(verdict)
+            }
+        }
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        match state {
+            GuardState::Rejected => Ok(false),
+            GuardState::Running { state, candidate } => {
+                let verdict = self.check.wait(state)?;
+                if verdict {
+                    self.accept(candidate);
+                }
+                Ok(verdict)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> Original {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec())
+    }
+
+    /// The text of each fixed node that isn't frozen
+    fn fixed(orig: &Original, protection: &Protection) -> Vec<String> {
+        let mut fixed = Vec::new();
+        let mut stack = vec![orig.tree.root_node()];
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children(&mut orig.tree.walk()));
+            let id = NodeId::new(&node);
+            if node.is_named() && protection.is_fixed(id) && !protection.is_frozen(id) {
+                fixed.push(String::from_utf8_lossy(&orig.text[node.byte_range()]).into_owned());
+            }
+        }
+        fixed
+    }
+
+    #[test]
+    fn test_protection() {
+        let src = "int f(void) { return 1; }\n// KEEP\nint main(void) { return f(); }\n";
+        let orig = parse(src);
+        let keep = Keep {
+            marker: Some("KEEP".to_string()),
+            ..Keep::default()
+        };
+        let frozen = keep.resolve(&orig, 0).unwrap();
+        let protection = Protection::new(&orig, &frozen);
+        assert_eq!(fixed(&orig, &protection), vec![src.to_string()]);
+        let frozen: Vec<&[u8]> = frozen.iter().map(|r| &orig.text[r.clone()]).collect();
+        assert_eq!(
+            frozen,
+            vec![
+                b"int main(void) { return f(); }".as_slice(),
+                b"// KEEP".as_slice(),
+            ]
+        );
+
+        // Just the first and last tokens are kept verbatim
+        let keep = Keep {
+            queries: vec!["(return_statement)".to_string()],
+            reduce_inside: true,
+            ..Keep::default()
+        };
+        let frozen = keep.resolve(&orig, 0).unwrap();
+        let frozen: Vec<&[u8]> = frozen.iter().map(|r| &orig.text[r.clone()]).collect();
+        assert_eq!(frozen, [b"return".as_slice(), b";", b"return", b";"]);
+        let protection = Protection::new(&orig, &keep.resolve(&orig, 0).unwrap());
+        assert_eq!(fixed(&orig, &protection).len(), 7);
+    }
+
+    /// Interesting if the text contains `ab`
+    struct HasAb;
+
+    impl Check for HasAb {
+        type State = bool;
+
+        fn start(&self, stdin: &[u8]) -> io::Result<bool> {
+            Ok(stdin.windows(2).any(|w| w == b"ab"))
+        }
+
+        fn cancel(&self, _state: bool) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn try_wait(&self, state: &mut bool) -> io::Result<Option<bool>> {
+            Ok(Some(*state))
+        }
+
+        fn wait(&self, state: bool) -> io::Result<bool> {
+            Ok(state)
+        }
+    }
+
+    #[test]
+    fn test_guard() {
+        let edit = |position, delete, insert: &[u8]| Edit {
+            position,
+            delete,
+            insert: insert.to_vec(),
+        };
+        let ranges = [2..4, 6..6];
+        assert_eq!(
+            shift(&ranges, &[edit(0, 1, b""), edit(4, 2, b"xyz")]),
+            Some(vec![1..3, 6..6])
+        );
+        assert_eq!(shift(&ranges, &[edit(3, 1, b"")]), None);
+
+        let edits = diff(b"ab; x; ab; x;", b"ab; ; ab; y;", MAX_DIFF).unwrap();
+        let edits: Vec<(usize, usize, &[u8])> = edits
+            .iter()
+            .map(|e| (e.position, e.delete, e.insert.as_slice()))
+            .collect();
+        assert_eq!(
+            edits,
+            vec![(4, 1, b"".as_slice()), (11, 1, b"y".as_slice())]
+        );
+
+        // Only the second `ab` is kept, though deleting it leaves the first.
+        // Changes on both sides of it are fine.
+        let orig = parse("ab; x; ab; x;");
+        let guard = Guard::new(&orig, vec![Range { start: 7, end: 9 }], &HasAb);
+        assert!(!guard.interesting(b"ab; x; x;").unwrap());
+        assert!(guard.interesting(b"ab; ; ab; ;").unwrap());
+        assert!(!guard.interesting(b"ab; ; ;").unwrap());
+        assert!(guard.interesting(b" ab;").unwrap());
+        assert_eq!(guard.frozen(b" ab;"), vec![1..3]);
+    }
+}
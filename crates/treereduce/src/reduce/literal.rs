@@ -0,0 +1,475 @@
+//! Shrink numeric and string literals.
+//
+// Literals are recognized by their text rather than their node kind, since
+// every grammar names them differently.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use tracing::{debug, info};
+use tree_sitter::Node;
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Literal {
+    Int {
+        prefix: String,
+        radix: u32,
+        value: u128,
+        suffix: String,
+    },
+    Float {
+        whole: String,
+        fraction: String,
+        exponent: String,
+        suffix: String,
+    },
+    Str {
+        prefix: String,
+        quote: char,
+        units: Vec<String>,
+    },
+}
+
+fn split_int(text: &str) -> Option<(&str, u32, &str)> {
+    let lower = text.to_ascii_lowercase();
+    if lower.starts_with("0x") {
+        Some((&text[..2], 16, &text[2..]))
+    } else if lower.starts_with("0b") {
+        Some((&text[..2], 2, &text[2..]))
+    } else if lower.starts_with("0o") {
+        Some((&text[..2], 8, &text[2..]))
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(("", 10, text))
+    } else {
+        None
+    }
+}
+
+fn parse_number(text: &str) -> Option<Literal> {
+    let (prefix, radix, rest) = split_int(text)?;
+    let digits_end = rest
+        .find(|c: char| !(c.is_digit(radix) || c == '_'))
+        .unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(digits_end);
+    if digits.is_empty() {
+        return None;
+    }
+    if radix == 10 && (suffix.starts_with('.') || suffix.starts_with(['e', 'E'])) {
+        let (fraction, rest) = if let Some(after_dot) = suffix.strip_prefix('.') {
+            let end = after_dot
+                .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+                .unwrap_or(after_dot.len());
+            after_dot.split_at(end)
+        } else {
+            ("", suffix)
+        };
+        let (exponent, suffix) = if rest.starts_with(['e', 'E']) {
+            let signed = rest[1..].starts_with(['+', '-']);
+            let start = if signed { 2 } else { 1 };
+            let end = rest[start..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |i| i + start);
+            rest.split_at(end)
+        } else {
+            ("", rest)
+        };
+        if !suffix.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        return Some(Literal::Float {
+            whole: digits.to_string(),
+            fraction: fraction.to_string(),
+            exponent: exponent.to_string(),
+            suffix: suffix.to_string(),
+        });
+    }
+    // Octal in C-like languages
+    let (prefix, radix) = if radix == 10 && digits.len() > 1 && digits.starts_with('0') {
+        ("0", 8)
+    } else {
+        (prefix, radix)
+    };
+    if !suffix.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let value = u128::from_str_radix(&digits.replace('_', ""), radix).ok()?;
+    Some(Literal::Int {
+        prefix: prefix.to_string(),
+        radix,
+        value,
+        suffix: suffix.to_string(),
+    })
+}
+
+/// Move up to `max` characters satisfying `f` from `chars` to `unit`.
+fn take(unit: &mut String, chars: &mut Peekable<Chars<'_>>, max: usize, f: fn(char) -> bool) {
+    let mut n = 0;
+    while n < max {
+        match chars.next_if(|c| f(*c)) {
+            Some(c) => unit.push(c),
+            None => break,
+        }
+        n += 1;
+    }
+}
+
+/// Split a string body into characters and escape sequences.
+fn units(body: &str) -> Option<Vec<String>> {
+    let mut units = Vec::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            units.push(c.to_string());
+            continue;
+        }
+        let mut unit = String::from(c);
+        let e = chars.next()?;
+        unit.push(e);
+        match e {
+            'x' => take(&mut unit, &mut chars, usize::MAX, |c| c.is_ascii_hexdigit()),
+            'u' if chars.peek() == Some(&'{') => {
+                take(&mut unit, &mut chars, usize::MAX, |c| c != '}');
+            }
+            'u' => take(&mut unit, &mut chars, 4, |c| c.is_ascii_hexdigit()),
+            'U' => take(&mut unit, &mut chars, 8, |c| c.is_ascii_hexdigit()),
+            '0'..='7' => take(&mut unit, &mut chars, 2, |c| ('0'..='7').contains(&c)),
+            _ => {}
+        }
+        if unit.starts_with("\\u{") {
+            unit.push(chars.next()?);
+        }
+        units.push(unit);
+    }
+    Some(units)
+}
+
+fn parse_string(text: &str) -> Option<Literal> {
+    let start = text.find(['"', '\'', '`'])?;
+    let (prefix, quoted) = text.split_at(start);
+    // Raw strings and the like
+    if !prefix.chars().all(|c| c.is_ascii_alphanumeric()) || prefix.contains(['r', 'R']) {
+        return None;
+    }
+    let quote = quoted.chars().next()?;
+    if quoted.len() < 2 || !quoted.ends_with(quote) {
+        return None;
+    }
+    let body = &quoted[1..quoted.len() - 1];
+    let units = units(body)?;
+    if units.iter().any(|u| u.starts_with(quote)) {
+        return None;
+    }
+    Some(Literal::Str {
+        prefix: prefix.to_string(),
+        quote,
+        units,
+    })
+}
+
+fn parse(text: &str) -> Option<Literal> {
+    parse_number(text).or_else(|| parse_string(text))
+}
+
+fn render_int(prefix: &str, radix: u32, value: u128, suffix: &str) -> String {
+    let digits = match radix {
+        2 => format!("{value:b}"),
+        8 => format!("{value:o}"),
+        16 => format!("{value:x}"),
+        _ => format!("{value}"),
+    };
+    let prefix = if radix == 8 && prefix == "0" && value == 0 {
+        ""
+    } else {
+        prefix
+    };
+    format!("{prefix}{digits}{suffix}")
+}
+
+/// Without the `.` if there's no fraction, e.g., `1e5` rather than `1.e5`
+fn render_float(whole: &str, fraction: &str, exponent: &str, suffix: &str) -> String {
+    if fraction.is_empty() {
+        format!("{whole}{exponent}{suffix}")
+    } else {
+        format!("{whole}.{fraction}{exponent}{suffix}")
+    }
+}
+
+fn render_str(prefix: &str, quote: char, units: &[String]) -> String {
+    format!("{prefix}{quote}{}{quote}", units.concat())
+}
+
+struct Shrinker<'a, T> {
+    orig: &'a Original,
+    check: &'a T,
+    edits: Edits,
+    /// Text of the literal currently being shrunk
+    current: String,
+    checks: usize,
+}
+
+impl<T: Check> Shrinker<'_, T> {
+    /// Keep the replacement if it's interesting.
+    fn try_replace(&mut self, node_id: NodeId, with: String) -> Result<bool, ReductionError> {
+        if with == self.current {
+            return Ok(true);
+        }
+        let candidate = self.edits.clone().replace_id(node_id, with.clone());
+        let mut text = Vec::with_capacity(self.orig.text.len());
+        render(&mut text, &self.orig.tree, &self.orig.text, &candidate)?;
+        self.checks += 1;
+        if self.check.interesting(&text)? {
+            self.edits = candidate;
+            self.current = with;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Binary search for the smallest interesting value.
+    fn shrink_int(
+        &mut self,
+        node_id: NodeId,
+        prefix: &str,
+        radix: u32,
+        value: u128,
+        suffix: &str,
+    ) -> Result<(), ReductionError> {
+        let int = |v| render_int(prefix, radix, v, suffix);
+        for small in [0, 1] {
+            if value <= small {
+                return Ok(());
+            }
+            if self.try_replace(node_id, int(small))? {
+                return Ok(());
+            }
+        }
+        // Invariant: lo is uninteresting, hi is interesting
+        let (mut lo, mut hi) = (1, value);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.try_replace(node_id, int(mid))? {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        Ok(())
+    }
+
+    fn shrink_float(
+        &mut self,
+        node_id: NodeId,
+        whole: &str,
+        fraction: &str,
+        exponent: &str,
+        suffix: &str,
+    ) -> Result<(), ReductionError> {
+        let float = |w: &str, f: &str, e: &str| render_float(w, f, e, suffix);
+        for small in ["0", "1"] {
+            if self.try_replace(node_id, float(small, "0", ""))? {
+                return Ok(());
+            }
+        }
+        let mut exponent = exponent;
+        if !exponent.is_empty() && self.try_replace(node_id, float(whole, fraction, ""))? {
+            exponent = "";
+        }
+        // Binary search for the shortest interesting fraction
+        let (mut lo, mut hi) = (0, fraction.len());
+        if hi > 0 && self.try_replace(node_id, float(whole, "0", exponent))? {
+            return Ok(());
+        }
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.try_replace(node_id, float(whole, &fraction[..mid], exponent))? {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delta debugging over the characters and escape sequences of a string.
+    fn shrink_str(
+        &mut self,
+        node_id: NodeId,
+        prefix: &str,
+        quote: char,
+        mut units: Vec<String>,
+    ) -> Result<(), ReductionError> {
+        if units.is_empty() || self.try_replace(node_id, render_str(prefix, quote, &[]))? {
+            return Ok(());
+        }
+        let mut chunks = 2;
+        while units.len() >= 2 {
+            let chunk_size = units.len().div_ceil(chunks);
+            let mut reduced = false;
+            let mut start = 0;
+            while start < units.len() {
+                let end = std::cmp::min(start + chunk_size, units.len());
+                let mut complement = units[..start].to_vec();
+                complement.extend_from_slice(&units[end..]);
+                if self.try_replace(node_id, render_str(prefix, quote, &complement))? {
+                    units = complement;
+                    reduced = true;
+                } else {
+                    start = end;
+                }
+            }
+            if reduced {
+                chunks = std::cmp::max(chunks - 1, 2);
+            } else if chunk_size == 1 {
+                break;
+            } else {
+                chunks = std::cmp::min(chunks * 2, units.len());
+            }
+        }
+        Ok(())
+    }
+
+    fn shrink(&mut self, node: &Node<'_>, literal: Literal) -> Result<(), ReductionError> {
+        let node_id = NodeId::new(node);
+        match literal {
+            Literal::Int {
+                prefix,
+                radix,
+                value,
+                suffix,
+            } => self.shrink_int(node_id, &prefix, radix, value, &suffix),
+            Literal::Float {
+                whole,
+                fraction,
+                exponent,
+                suffix,
+            } => self.shrink_float(node_id, &whole, &fraction, &exponent, &suffix),
+            Literal::Str {
+                prefix,
+                quote,
+                units,
+            } => self.shrink_str(node_id, &prefix, quote, units),
+        }
+    }
+}
+
+fn literals<'a>(orig: &'a Original) -> Vec<(Node<'a>, Literal)> {
+    let mut found = Vec::new();
+    let mut queue = vec![orig.tree.root_node()];
+    while let Some(node) = queue.pop() {
+        if node.is_named() && !node.is_extra() && !node.has_error() {
+            let literal = std::str::from_utf8(&orig.text[node.byte_range()])
+                .ok()
+                .and_then(parse);
+            if let Some(lit) = literal {
+                found.push((node, lit));
+                continue;
+            }
+        }
+        queue.extend(node.children(&mut orig.tree.walk()));
+    }
+    found.sort_by_key(|(node, _)| node.start_byte());
+    found
+}
+
+/// Each literal, and up to `count` other values of the same type
+pub(super) fn alternatives(orig: &Original, count: usize) -> Vec<(Node<'_>, Vec<String>)> {
+    literals(orig)
+        .into_iter()
+        .map(|(node, literal)| {
+            let mut alternatives: Vec<String> = match literal {
+                Literal::Int {
+                    prefix,
+                    radix,
+                    suffix,
+                    ..
+                } => [0, 1, 2]
+                    .into_iter()
+                    .map(|v| render_int(&prefix, radix, v, &suffix))
+                    .collect(),
+                Literal::Float { suffix, .. } => ["0.0", "1.5", "2.0"]
+                    .into_iter()
+                    .map(|f| format!("{f}{suffix}"))
+                    .collect(),
+                Literal::Str { prefix, quote, .. } => [&[][..], &["a"], &["zz"]]
+                    .into_iter()
+                    .map(|units| {
+                        let units: Vec<String> = units.iter().map(ToString::to_string).collect();
+                        render_str(&prefix, quote, &units)
+                    })
+                    .collect(),
+            };
+            let text = &orig.text[node.byte_range()];
+            alternatives.retain(|a| a.as_bytes() != text);
+            alternatives.truncate(count);
+            (node, alternatives)
+        })
+        .collect()
+}
+
+/// Shrink each numeric literal towards zero, each floating-point literal
+/// towards fewer digits, and each string literal towards fewer characters.
+pub(super) fn shrink_literals<T: Check>(
+    orig: &Original,
+    check: &T,
+) -> Result<Edits, ReductionError> {
+    let literals = literals(orig);
+    info!("Trying to shrink {} literals", literals.len());
+    let mut shrinker = Shrinker {
+        orig,
+        check,
+        edits: Edits::new(),
+        current: String::new(),
+        checks: 0,
+    };
+    for (node, literal) in literals {
+        shrinker.current = String::from_utf8_lossy(&orig.text[node.byte_range()]).into_owned();
+        shrinker.shrink(&node, literal)?;
+    }
+    debug!("Shrinking literals took {} checks", shrinker.checks);
+    Ok(shrinker.edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("0x1Fu"),
+            Some(Literal::Int {
+                prefix: "0x".to_string(),
+                radix: 16,
+                value: 31,
+                suffix: "u".to_string()
+            })
+        );
+        assert_eq!(render_int("0x", 16, 4, "u"), "0x4u");
+        assert_eq!(
+            parse("1.25e-3f"),
+            Some(Literal::Float {
+                whole: "1".to_string(),
+                fraction: "25".to_string(),
+                exponent: "e-3".to_string(),
+                suffix: "f".to_string()
+            })
+        );
+        assert_eq!(render_float("1", "", "e5", ""), "1e5");
+        assert_eq!(render_float("1", "5", "", "f"), "1.5f");
+        let Some(Literal::Str { units, .. }) = parse(r#"L"a\n\x41b\"""#) else {
+            panic!()
+        };
+        assert_eq!(units, ["a", "\\n", "\\x41b", "\\\""]);
+        assert_eq!(parse("foo"), None);
+        assert_eq!(parse(r#""a" "b""#), None);
+        assert_eq!(parse(r##"r#"a"#"##), None);
+    }
+}
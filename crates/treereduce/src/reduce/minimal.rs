@@ -0,0 +1,163 @@
+//! Check that the result is 1-minimal, i.e., that no single node can be
+//! deleted or replaced.
+//
+// The main passes skip small nodes (`min_reduction`) and, by default, nodes
+// that the grammar says are required, so their results can usually be reduced
+// a bit more. This tries every remaining node, one at a time, and takes any
+// reduction it finds.
+
+use std::collections::{HashMap, HashSet};
+
+use tracing::{debug, info};
+use tree_sitter::{Node, Parser};
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+use crate::stats::Essential;
+
+use super::error::ReductionError;
+
+/// Deletion, then the replacements for the node's kind that are smaller than it
+fn candidates(
+    node: &Node<'_>,
+    text: &[u8],
+    replacements: &HashMap<&'static str, &'static [&'static str]>,
+    default_replacements: &HashMap<String, String>,
+) -> Vec<String> {
+    let size = node.byte_range().len();
+    let mut candidates = vec![String::new()];
+    let kind = node.kind();
+    let replacements = replacements
+        .get(kind)
+        .map(|rs| rs.iter().map(|r| (*r).to_string()).collect())
+        .or_else(|| default_replacements.get(kind).map(|r| vec![r.clone()]))
+        .unwrap_or_default();
+    for r in replacements {
+        if r.len() < size && r.as_bytes() != &text[node.byte_range()] {
+            candidates.push(r);
+        }
+    }
+    candidates
+}
+
+/// Try deleting or replacing each node that starts at or after `from` in
+/// turn, and return the first interesting result and where the node was.
+fn first_reduction<T: Check>(
+    orig: &Original,
+    from: usize,
+    check: &T,
+    replacements: &HashMap<&'static str, &'static [&'static str]>,
+    default_replacements: &HashMap<String, String>,
+) -> Result<Option<(Vec<u8>, usize)>, ReductionError> {
+    // Nested nodes with the same extent produce the same text
+    let mut tried = HashSet::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.end_byte() <= from && !node.byte_range().is_empty() {
+            continue;
+        }
+        let children: Vec<_> = node.children(&mut orig.tree.walk()).collect();
+        stack.extend(children.into_iter().rev());
+        if node.byte_range().is_empty() || node.start_byte() < from {
+            continue;
+        }
+        for candidate in candidates(&node, &orig.text, replacements, default_replacements) {
+            let edits = Edits::new().replace_id(NodeId::new(&node), candidate);
+            let mut text = Vec::with_capacity(orig.text.len());
+            render(&mut text, &orig.tree, &orig.text, &edits)?;
+            if !tried.insert(text.clone()) {
+                continue;
+            }
+            if check.interesting(&text)? {
+                debug!("Reduced {} at byte {}", node.kind(), node.start_byte());
+                return Ok(Some((text, node.start_byte())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reduce until no single node can be deleted or replaced. Each sweep picks up
+/// after the last reduction, rather than starting over; once one reaches the
+/// end, another starts from the beginning, until one finds nothing.
+pub(super) fn reduce<T: Check>(
+    orig: &Original,
+    check: &T,
+    replacements: &HashMap<&'static str, &'static [&'static str]>,
+    default_replacements: &HashMap<String, String>,
+) -> Result<Vec<u8>, ReductionError> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&orig.tree.language())
+        .expect("Failed to set tree-sitter parser language");
+    let mut current = orig.clone();
+    let mut found = 0;
+    let mut from = 0;
+    // Whether the current sweep has found any reductions
+    let mut progress = false;
+    loop {
+        match first_reduction(&current, from, check, replacements, default_replacements)? {
+            Some((text, at)) => {
+                found += 1;
+                progress = true;
+                from = at;
+                let tree = parser.parse(&text, None).expect("Failed to parse");
+                current = Original::new(tree, text);
+            }
+            None if progress => {
+                progress = false;
+                from = 0;
+            }
+            None => break,
+        }
+    }
+    if found == 0 {
+        info!("Result is 1-minimal");
+    } else {
+        info!("Result was not 1-minimal, made {} more reductions", found);
+    }
+    Ok(current.text)
+}
+
+/// Each named node of the `file`th file, all of which are essential once
+/// [`reduce`] is done
+pub(super) fn essential(orig: &Original, file: usize) -> Vec<Essential> {
+    let mut essential = Vec::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let children: Vec<_> = node.named_children(&mut orig.tree.walk()).collect();
+        stack.extend(children.into_iter().rev());
+        if node.byte_range().is_empty() {
+            continue;
+        }
+        essential.push(Essential {
+            file,
+            kind: node.kind().to_string(),
+            start: node.start_position(),
+            text: String::from_utf8_lossy(&orig.text[node.byte_range()]).into_owned(),
+        });
+    }
+    essential
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::CmdCheck;
+
+    #[test]
+    fn test_reduce() {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int x; int y; int z;";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let check = CmdCheck::exit_zero("grep", &["-q", "y"]);
+        let reduced = reduce(&orig, &check, &HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(String::from_utf8(reduced).unwrap().trim(), "y");
+    }
+}
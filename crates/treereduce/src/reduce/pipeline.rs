@@ -0,0 +1,194 @@
+//! A declarative sequence of passes, each with its own settings.
+//!
+//! Pipelines can be written as JSON, e.g.,
+//!
+//! ```json
+//! {
+//!   "loop": true,
+//!   "passes": [
+//!     {"pass": "tree", "passes": 1, "min_reduction": 64, "replacements": false},
+//!     {"pass": "tree", "passes": 2},
+//!     {"pass": "tree", "delete_non_optional": true, "min_reduction": 1},
+//!     {"pass": "ddmin", "granularities": ["line", "token"]},
+//!     {"pass": "shrink_literals"},
+//!     {"pass": "tidy"}
+//!   ]
+//! }
+//! ```
+
+use serde::Deserialize;
+
+use crate::metric::Metric;
+
+use super::{Config, Granularity};
+
+/// Settings for a pass that deletes and replaces nodes using the task heap.
+///
+/// Settings that are `None` are taken from the [`Config`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TreePass {
+    /// How many times to run the pass, or until no progress is made if `None`
+    pub passes: Option<usize>,
+    /// Stop repeating the pass once a run makes the program smaller by less
+    /// than this (in the pass's [`Metric`])
+    pub min_progress: usize,
+    pub metric: Option<Metric>,
+    pub jobs: Option<usize>,
+    pub min_reduction: Option<usize>,
+    pub delete_non_optional: Option<bool>,
+    /// Whether to try replacing nodes (rather than just deleting them)
+    pub replacements: Option<bool>,
+    pub reuse: Option<usize>,
+    /// Whether to delete parameters along with the matching arguments
+    pub arity: Option<bool>,
+    /// Whether to inline functions
+    pub inline: Option<bool>,
+    /// Whether to run plugins
+    pub plugins: Option<bool>,
+}
+
+impl TreePass {
+    pub(super) fn configure<T: Clone>(&self, conf: &Config<T>) -> Config<T> {
+        let mut conf = conf.clone();
+        if let Some(jobs) = self.jobs {
+            conf.jobs = jobs;
+        }
+        if let Some(min_reduction) = self.min_reduction {
+            conf.min_reduction = min_reduction;
+        }
+        if let Some(delete_non_optional) = self.delete_non_optional {
+            conf.delete_non_optional = delete_non_optional;
+        }
+        if let Some(metric) = self.metric {
+            conf.metric = metric;
+        }
+        if let Some(reuse) = self.reuse {
+            conf.reuse = reuse;
+        }
+        if self.replacements == Some(false) {
+            conf.replacements.clear();
+            conf.default_replacements.clear();
+            conf.rewrites.clear();
+            conf.reuse = 0;
+        }
+        if let Some(arity) = self.arity {
+            conf.arity = arity;
+        }
+        if self.inline == Some(false) {
+            conf.inline = None;
+        }
+        if self.plugins == Some(false) {
+            conf.plugins.clear();
+        }
+        conf
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "pass", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Pass {
+    /// Delete and replace nodes using the task heap
+    Tree(TreePass),
+    /// Delta-debug lines and tokens inside parse errors, if there are any
+    Fallback,
+    /// Delta-debug the whole text at each granularity, until no progress
+    Ddmin {
+        granularities: Vec<Granularity>,
+    },
+    ShrinkLiterals,
+    Rename,
+    /// Delete comments and normalize whitespace
+    Tidy,
+    /// Delete and replace single nodes until the result is 1-minimal, and
+    /// report the nodes that remain
+    VerifyMinimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub passes: Vec<Pass>,
+    /// Repeat the whole pipeline until it makes no progress
+    #[serde(default, rename = "loop")]
+    pub fixpoint: bool,
+}
+
+impl Pipeline {
+    /// Parse a pipeline written as JSON.
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is malformed, or doesn't describe a pipeline.
+    pub fn parse(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The pipeline described by the flags in the [`Config`]: `max_passes`
+    /// runs of the tree pass, then each post-pass that's enabled.
+    pub fn from_config<T>(conf: &Config<T>, max_passes: Option<usize>) -> Self {
+        let mut passes = vec![Pass::Tree(TreePass {
+            passes: max_passes,
+            ..TreePass::default()
+        })];
+        if conf.fallback {
+            passes.push(Pass::Fallback);
+        }
+        if !conf.ddmin.is_empty() {
+            passes.push(Pass::Ddmin {
+                granularities: conf.ddmin.clone(),
+            });
+        }
+        if conf.shrink_literals {
+            passes.push(Pass::ShrinkLiterals);
+        }
+        if conf.rename {
+            passes.push(Pass::Rename);
+        }
+        if conf.tidy {
+            passes.push(Pass::Tidy);
+        }
+        if conf.verify_minimal {
+            passes.push(Pass::VerifyMinimal);
+        }
+        Pipeline {
+            passes,
+            fixpoint: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let pipeline = Pipeline::parse(
+            r#"{"loop": true, "passes": [
+                 {"pass": "tree", "passes": 1, "min_reduction": 64, "replacements": false},
+                 {"pass": "ddmin", "granularities": ["line", "byte"]},
+                 {"pass": "tidy"}
+               ]}"#,
+        )
+        .unwrap();
+        assert!(pipeline.fixpoint);
+        assert_eq!(
+            pipeline.passes,
+            vec![
+                Pass::Tree(TreePass {
+                    passes: Some(1),
+                    min_reduction: Some(64),
+                    replacements: Some(false),
+                    ..TreePass::default()
+                }),
+                Pass::Ddmin {
+                    granularities: vec![Granularity::Line, Granularity::Byte]
+                },
+                Pass::Tidy,
+            ]
+        );
+        assert!(Pipeline::parse(r#"{"passes": [{"pass": "tree", "bogus": 1}]}"#).is_err());
+        assert!(Pipeline::parse(r#"{"passes": [{"pass": "bogus"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! External commands that suggest transformations.
+//
+// The plugin receives a JSON object on stdin:
+//
+//     {"text": "...", "tree": {"id": 0, "kind": "...", "named": true,
+//                              "start": 0, "end": 10, "children": [...]}}
+//
+// where `tree` is `null` unless requested. It prints candidates on stdout, one
+// JSON object per line:
+//
+//     {"edits": [{"node": 3, "replacement": ""},
+//                {"start": 5, "end": 8, "replacement": "0"}]}
+//
+// Each edit replaces either a node (by its `id` from the tree) or a byte range
+// (which must be exactly the range of some node). All of the edits in a
+// candidate are tried together.
+
+use std::io;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use tree_sitter::Node;
+
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+
+/// An external command that suggests transformations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plugin {
+    /// The program and its arguments
+    pub command: Vec<String>,
+    /// Whether to send the parse tree
+    pub tree: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct TreeNode {
+    id: usize,
+    kind: &'static str,
+    named: bool,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Target {
+    Node { node: usize },
+    Range { start: usize, end: usize },
+}
+
+#[derive(Debug, Deserialize)]
+struct Edit {
+    #[serde(flatten)]
+    target: Target,
+    #[serde(default)]
+    replacement: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Candidate {
+    edits: Vec<Edit>,
+}
+
+/// Write the tree as JSON, numbering the nodes in pre-order. Trees can be
+/// deep, so this doesn't recurse.
+fn write_tree<'tree>(
+    out: &mut Vec<u8>,
+    tree: &'tree tree_sitter::Tree,
+    nodes: &mut Vec<Node<'tree>>,
+) -> serde_json::Result<()> {
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        let fields = serde_json::to_vec(&TreeNode {
+            id: nodes.len(),
+            kind: node.kind(),
+            named: node.is_named(),
+            start: node.start_byte(),
+            end: node.end_byte(),
+        })?;
+        nodes.push(node);
+        // Leave off the closing brace, to add the children
+        out.extend_from_slice(&fields[..fields.len() - 1]);
+        out.extend_from_slice(b",\"children\":[");
+        if cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            out.extend_from_slice(b"]}");
+            if cursor.goto_next_sibling() {
+                out.push(b',');
+                break;
+            }
+            if !cursor.goto_parent() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The JSON input to the plugin
+fn input<'tree>(
+    orig: &'tree Original,
+    text: &str,
+    tree: bool,
+    nodes: &mut Vec<Node<'tree>>,
+) -> serde_json::Result<Vec<u8>> {
+    let mut out = b"{\"text\":".to_vec();
+    serde_json::to_writer(&mut out, text)?;
+    out.extend_from_slice(b",\"tree\":");
+    if tree {
+        write_tree(&mut out, &orig.tree, nodes)?;
+    } else {
+        out.extend_from_slice(b"null");
+    }
+    out.push(b'}');
+    Ok(out)
+}
+
+fn resolve<'tree>(
+    orig: &'tree Original,
+    nodes: &[Node<'tree>],
+    target: &Target,
+) -> Option<Node<'tree>> {
+    match target {
+        Target::Node { node } => nodes.get(*node).copied(),
+        Target::Range { start, end } => orig
+            .tree
+            .root_node()
+            .descendant_for_byte_range(*start, *end)
+            .filter(|n| n.start_byte() == *start && n.end_byte() == *end),
+    }
+}
+
+/// Replacements to try all together
+type Replacements = Vec<(NodeId, String)>;
+
+/// Run the plugin, and collect the candidates it suggests.
+pub(super) fn candidates(
+    orig: &Original,
+    plugin: &Plugin,
+) -> Result<Vec<Replacements>, ReductionError> {
+    let Some((cmd, args)) = plugin.command.split_first() else {
+        return Ok(Vec::new());
+    };
+    let Ok(text) = std::str::from_utf8(&orig.text) else {
+        warn!("Not running plugin {}, the input isn't UTF-8", cmd);
+        return Ok(Vec::new());
+    };
+    let mut nodes = Vec::new();
+    let input = input(orig, text, plugin.tree, &mut nodes).map_err(io::Error::from)?;
+    let mut child = Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take();
+    // Write the input while reading the output, in case the plugin doesn't
+    // read all of its input before writing
+    let (written, output) = thread::scope(|s| {
+        let writer = s.spawn(move || {
+            let Some(mut stdin) = stdin else {
+                return Ok(());
+            };
+            stdin.write_all(&input).and_then(|()| stdin.flush())
+        });
+        let output = child.wait_with_output();
+        let written = writer
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e));
+        (written, output)
+    });
+    match written {
+        // Plugins don't have to read their input
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        r => r?,
+    }
+    let output = output?;
+    if !output.status.success() {
+        warn!("Plugin {} failed with {}", cmd, output.status);
+    }
+
+    let mut candidates = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let candidate: Candidate = match serde_json::from_str(line) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Bad output from plugin {}: {}", cmd, e);
+                continue;
+            }
+        };
+        let mut edits = Vec::new();
+        for edit in candidate.edits {
+            let Some(node) = resolve(orig, &nodes, &edit.target) else {
+                debug!("Plugin {} suggested an edit that isn't to a node", cmd);
+                edits.clear();
+                break;
+            };
+            edits.push((NodeId::new(&node), edit.replacement));
+        }
+        if !edits.is_empty() {
+            candidates.push(edits);
+        }
+    }
+    debug!("Plugin {} suggested {} candidates", cmd, candidates.len());
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int x = 1000;";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let output = r#"{"edits": [{"start": 8, "end": 12, "replacement": "0"}]}
+{"edits": [{"start": 7, "end": 12}]}
+not json"#;
+        let plugin = Plugin {
+            command: vec!["echo".to_string(), output.to_string()],
+            tree: true,
+        };
+        let found = candidates(&orig, &plugin).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0][0].1, "0");
+
+        // More than a pipe buffer each way
+        let src = "int x;\n".repeat(20_000);
+        let orig = Original::new(parser.parse(&src, None).unwrap(), src.as_bytes().to_vec());
+        let script = r#"yes "" | head -n 100000; cat >/dev/null; echo '{"edits": [{"start": 0, "end": 3}]}'"#;
+        let plugin = Plugin {
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            tree: false,
+        };
+        assert_eq!(candidates(&orig, &plugin).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_input() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int x;";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let mut nodes = Vec::new();
+        let json = input(&orig, src, true, &mut nodes).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["text"], src);
+        let declaration = &value["tree"]["children"][0];
+        assert_eq!(declaration["kind"], "declaration");
+        assert_eq!(declaration["children"][1]["kind"], "identifier");
+        let id = declaration["children"][1]["id"].as_u64().unwrap();
+        assert_eq!(nodes[usize::try_from(id).unwrap()].kind(), "identifier");
+
+        let json = input(&orig, src, false, &mut Vec::new()).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert!(value["tree"].is_null());
+    }
+}
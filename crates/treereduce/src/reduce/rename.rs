@@ -0,0 +1,285 @@
+//! Rename identifiers to short, canonical names.
+//
+// The scope analysis is rough: a name that's declared (as a variable or
+// parameter) in each function it appears in is treated as a separate binding in
+// each of them, every other name is a single binding, and all of the
+// occurrences of a binding are renamed together.
+
+use std::collections::{BTreeMap, HashSet};
+
+use tracing::info;
+use tree_sitter::{Language, Node};
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+use super::tidy::accept;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Role {
+    Function,
+    Type,
+    Variable,
+}
+
+impl Role {
+    fn names(self) -> impl Iterator<Item = String> {
+        let (singles, prefix): (&[char], &str) = match self {
+            Role::Function => (&[], "f"),
+            Role::Type => (&['T', 'U', 'V', 'W'], "T"),
+            Role::Variable => (
+                &[
+                    'a', 'b', 'c', 'd', 'e', 'g', 'h', 'i', 'j', 'k', 'm', 'n', 'p', 'q', 'r', 's',
+                    'u', 'v', 'w', 'x', 'y', 'z',
+                ],
+                "v",
+            ),
+        };
+        singles
+            .iter()
+            .map(ToString::to_string)
+            .chain((1..).map(move |i| format!("{prefix}{i}")))
+    }
+}
+
+#[derive(Debug)]
+struct Binding {
+    name: String,
+    role: Role,
+    occurrences: Vec<NodeId>,
+}
+
+fn is_identifier_kind(kind: &str) -> bool {
+    kind.contains("identifier") || kind == "ident" || kind == "name"
+}
+
+pub(super) fn is_word(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn role(node: &Node<'_>) -> Role {
+    if node.kind().contains("type") {
+        return Role::Type;
+    }
+    let Some(parent) = node.parent() else {
+        return Role::Variable;
+    };
+    let p = parent.kind();
+    let callee = ["function", "name", "declarator"]
+        .iter()
+        .any(|field| parent.child_by_field_name(field).as_ref() == Some(node));
+    if callee && (p.contains("function") || p.contains("call") || p.contains("method")) {
+        Role::Function
+    } else {
+        Role::Variable
+    }
+}
+
+/// The start of the nearest enclosing function, if any
+fn scope(node: &Node<'_>) -> Option<usize> {
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        let kind = p.kind();
+        let function = ["function", "method", "closure", "lambda"]
+            .iter()
+            .any(|k| kind.contains(k));
+        if function && p.child_by_field_name("body").is_some() {
+            return Some(p.start_byte());
+        }
+        parent = p.parent();
+    }
+    None
+}
+
+/// Whether this occurrence of an identifier declares a variable or parameter
+fn declares(node: &Node<'_>) -> bool {
+    role(node) == Role::Variable
+        && node.parent().is_some_and(|p| {
+            ["declarator", "declaration", "parameter", "pattern"]
+                .iter()
+                .any(|k| p.kind().contains(k))
+        })
+}
+
+/// Keywords and other word-like tokens of the language
+fn reserved_words(language: &Language) -> HashSet<&'static str> {
+    let mut words = HashSet::new();
+    for id in 0..language.node_kind_count() {
+        let Ok(id) = u16::try_from(id) else {
+            break;
+        };
+        if language.node_kind_is_named(id) || !language.node_kind_is_visible(id) {
+            continue;
+        }
+        if let Some(kind) = language.node_kind_for_id(id) {
+            if is_word(kind) {
+                words.insert(kind);
+            }
+        }
+    }
+    words
+}
+
+fn bindings(orig: &Original) -> (Vec<Binding>, HashSet<String>) {
+    let mut occurrences = Vec::new();
+    let mut words = HashSet::new();
+    let mut queue = vec![orig.tree.root_node()];
+    while let Some(node) = queue.pop() {
+        queue.extend(node.children(&mut orig.tree.walk()));
+        if node.child_count() != 0 || node.is_missing() {
+            continue;
+        }
+        let Ok(text) = std::str::from_utf8(&orig.text[node.byte_range()]) else {
+            continue;
+        };
+        if !is_word(text) {
+            continue;
+        }
+        words.insert(text.to_string());
+        if !node.is_named() || !is_identifier_kind(node.kind()) || node.has_error() {
+            continue;
+        }
+        occurrences.push((text, scope(&node), declares(&node), node));
+    }
+
+    // Names that aren't declared locally in each function they appear in
+    let declared: HashSet<(&str, Option<usize>)> = occurrences
+        .iter()
+        .filter(|(_, scope, declares, _)| scope.is_some() && *declares)
+        .map(|(text, scope, _, _)| (*text, *scope))
+        .collect();
+    let shared: HashSet<&str> = occurrences
+        .iter()
+        .filter(|(text, scope, _, _)| !declared.contains(&(*text, *scope)))
+        .map(|(text, _, _, _)| *text)
+        .collect();
+
+    let mut by_binding: BTreeMap<(&str, Option<usize>), Binding> = BTreeMap::new();
+    for (text, scope, _, node) in occurrences {
+        let scope = if shared.contains(text) { None } else { scope };
+        let binding = by_binding.entry((text, scope)).or_insert_with(|| Binding {
+            name: text.to_string(),
+            role: Role::Variable,
+            occurrences: Vec::new(),
+        });
+        binding.role = std::cmp::min(binding.role, role(&node));
+        binding.occurrences.push(NodeId::new(&node));
+    }
+    let mut bindings: Vec<Binding> = by_binding.into_values().collect();
+    // Most frequent first, so they get the shortest names
+    bindings.sort_by_key(|b| std::cmp::Reverse(b.occurrences.len()));
+    (bindings, words)
+}
+
+/// Pick a new name for each binding that doesn't collide with a keyword or
+/// another word in the program.
+fn assign(bindings: Vec<Binding>, mut taken: HashSet<String>) -> Vec<(Binding, String)> {
+    let mut assigned = Vec::new();
+    for binding in bindings {
+        if binding.name.len() == 1 {
+            continue;
+        }
+        let Some(new) = binding.role.names().find(|n| !taken.contains(n)) else {
+            continue;
+        };
+        if new.len() > binding.name.len() {
+            continue;
+        }
+        taken.insert(new.clone());
+        assigned.push((binding, new));
+    }
+    assigned
+}
+
+fn with_renames(edits: &Edits, renames: &[(Binding, String)]) -> Edits {
+    let mut edits = edits.clone();
+    for (binding, new) in renames {
+        for id in &binding.occurrences {
+            edits = edits.replace_id(*id, new.clone());
+        }
+    }
+    edits
+}
+
+/// The occurrences of each identifier, and up to `count` fresh names for it
+pub(super) fn alternatives(orig: &Original, count: usize) -> Vec<(Vec<NodeId>, Vec<String>)> {
+    let (bindings, words) = bindings(orig);
+    let mut taken: HashSet<String> = reserved_words(&orig.tree.language())
+        .into_iter()
+        .map(String::from)
+        .collect();
+    taken.extend(words);
+    bindings
+        .into_iter()
+        .map(|b| {
+            let names = b.role.names().filter(|n| !taken.contains(n)).take(count);
+            (b.occurrences, names.collect())
+        })
+        .collect()
+}
+
+/// Rename identifiers consistently, checking the renames in groups.
+pub(super) fn rename<T: Check>(orig: &Original, check: &T) -> Result<Edits, ReductionError> {
+    let (bindings, words) = bindings(orig);
+    let mut taken: HashSet<String> = reserved_words(&orig.tree.language())
+        .into_iter()
+        .map(String::from)
+        .collect();
+    taken.extend(words);
+    let renames = assign(bindings, taken);
+    info!("Trying to rename {} identifiers", renames.len());
+
+    let accepted = accept(renames, check, |accepted, group| {
+        let edits = with_renames(&with_renames(&Edits::new(), accepted), group);
+        let mut text = Vec::with_capacity(orig.text.len());
+        render(&mut text, &orig.tree, &orig.text, &edits)?;
+        Ok(text)
+    })?;
+    Ok(with_renames(&Edits::new(), &accepted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let mut parser = tree_sitter::Parser::new();
+        let language: Language = tree_sitter_c::LANGUAGE.into();
+        parser.set_language(&language).unwrap();
+        let src = "typedef int my_int; my_int helper(my_int value) { return value + a; }";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let (bindings, mut words) = bindings(&orig);
+        words.extend(reserved_words(&language).into_iter().map(String::from));
+        let assigned: Vec<_> = assign(bindings, words)
+            .into_iter()
+            .map(|(b, n)| (b.name, n))
+            .collect();
+        assert!(assigned.contains(&("my_int".to_string(), "T".to_string())));
+        assert!(assigned.contains(&("helper".to_string(), "f1".to_string())));
+        assert!(assigned.contains(&("value".to_string(), "b".to_string())));
+    }
+
+    #[test]
+    fn test_scopes() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int total; int f(int count) { return count + total; } \
+                   int g(void) { int count = 1; return count + total; }";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let (bindings, _) = bindings(&orig);
+        let count = |name: &str| bindings.iter().filter(|b| b.name == name).count();
+        assert_eq!(count("count"), 2);
+        assert_eq!(count("total"), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::{BTreeSet, HashMap};
+
+use tree_sitter::{Node, Tree};
+
+use crate::node_types::NodeTypes;
+
+/// The smallest few distinct texts of each kind (and supertype) of node
+#[derive(Debug, Default)]
+pub(super) struct Reusable {
+    smallest: HashMap<String, BTreeSet<(usize, String)>>,
+}
+
+impl Reusable {
+    /// Consider the nodes of a file
+    pub(super) fn add(&mut self, tree: &Tree, text: &[u8], node_types: &NodeTypes, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let mut queue = vec![tree.root_node()];
+        while let Some(node) = queue.pop() {
+            queue.extend(node.children(&mut tree.walk()));
+            if node.has_error() || node.is_missing() || node.is_extra() {
+                continue;
+            }
+            self.insert(node.kind(), &node, text, count);
+            for supertype in node_types.supertypes(node.kind()) {
+                self.insert(supertype, &node, text, count);
+            }
+        }
+    }
+
+    fn insert(&mut self, kind: &str, node: &Node<'_>, text: &[u8], count: usize) {
+        let Ok(s) = std::str::from_utf8(&text[node.byte_range()]) else {
+            return;
+        };
+        if s.trim().is_empty() {
+            return;
+        }
+        let set = self.smallest.entry(kind.to_string()).or_default();
+        if set.len() >= count && set.last().is_some_and(|(len, _)| *len <= s.len()) {
+            return;
+        }
+        set.insert((s.len(), s.to_string()));
+        if set.len() > count {
+            set.pop_last();
+        }
+    }
+
+    /// The smallest texts of the given kind, smallest first
+    pub(super) fn get(&self, kind: &str) -> impl Iterator<Item = &str> {
+        self.smallest
+            .get(kind)
+            .into_iter()
+            .flat_map(|set| set.iter().map(|(_, s)| s.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reusable() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int f(void) { return 100 + x * 20 + 3; }";
+        let tree = parser.parse(src, None).unwrap();
+        let nt = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        let mut reusable = Reusable::default();
+        reusable.add(&tree, src.as_bytes(), &nt, 2);
+        assert_eq!(
+            reusable.get("number_literal").collect::<Vec<_>>(),
+            ["3", "20"]
+        );
+        assert_eq!(reusable.get("expression").collect::<Vec<_>>(), ["3", "x"]);
+        assert_eq!(reusable.get("if_statement").count(), 0);
+    }
+}
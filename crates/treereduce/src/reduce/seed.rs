@@ -0,0 +1,198 @@
+//! Start from edits and hints provided by the user.
+//!
+//! Seeds can be written as JSON, e.g.,
+//!
+//! ```json
+//! {
+//!   "edits": [
+//!     {"lines": [10, 42]},
+//!     {"query": "(function_definition declarator: (_ declarator: (identifier) @n) (#eq? @n \"log\"))"},
+//!     {"bytes": [1200, 1240], "replacement": "0"}
+//!   ],
+//!   "hints": [{"lines": [100, 120]}]
+//! }
+//! ```
+//
+// Each edit deletes (or replaces) the outermost nodes inside of its target.
+// All of the edits are checked together, once, and used as the starting point
+// of the first tree pass if they're interesting. Nodes inside of the hints are
+// reduced before anything else.
+
+use std::ops::Range;
+
+use serde::Deserialize;
+use tracing::{info, warn};
+use tree_sitter::{Node, QueryError};
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+use super::keep::{self, Protection};
+
+/// Name of the capture for the nodes to edit or reduce first
+const TARGET: &str = "target";
+
+/// Part of the program, by lines (1-based, inclusive), bytes (0-based, end
+/// exclusive), or tree-sitter query (the nodes captured as `@target`, or
+/// matched if there's no such capture)
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Target {
+    Lines { lines: (usize, usize) },
+    Bytes { bytes: (usize, usize) },
+    Query { query: String },
+}
+
+impl Target {
+    fn ranges(&self, orig: &Original) -> Result<Vec<Range<usize>>, QueryError> {
+        let len = orig.text.len();
+        let line_start = |line: usize| {
+            if line <= 1 {
+                return 0;
+            }
+            orig.text
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .nth(line - 2)
+                .map_or(len, |(i, _)| i + 1)
+        };
+        Ok(match self {
+            Target::Lines {
+                lines: (start, end),
+            } => std::iter::once(line_start(*start)..line_start(end + 1)).collect(),
+            Target::Bytes {
+                bytes: (start, end),
+            } => std::iter::once((*start).min(len)..(*end).min(len)).collect(),
+            Target::Query { query } => keep::captured(orig, query, TARGET)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SeedEdit {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Text to replace the (first) node with, or `None` to delete it
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+/// Edits to start from, and where to reduce first
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Seed {
+    pub edits: Vec<SeedEdit>,
+    pub hints: Vec<Target>,
+}
+
+impl Seed {
+    /// Parse a seed written as JSON.
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is malformed, or doesn't describe a seed.
+    pub fn parse(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Byte ranges of the hints
+    pub(super) fn hints(&self, orig: &Original) -> Result<Vec<Range<usize>>, QueryError> {
+        let mut ranges = Vec::new();
+        for hint in &self.hints {
+            ranges.extend(hint.ranges(orig)?);
+        }
+        Ok(ranges)
+    }
+
+    /// The edits, if they're interesting, or else no edits.
+    pub(super) fn edits<T: Check>(
+        &self,
+        orig: &Original,
+        check: &T,
+        protection: &Protection,
+    ) -> Result<Edits, ReductionError> {
+        let mut edits = Edits::new();
+        let mut count = 0;
+        for edit in &self.edits {
+            for range in edit.target.ranges(orig)? {
+                let nodes = outermost(orig, &range);
+                if nodes.iter().any(|n| protection.is_fixed(NodeId::new(n))) {
+                    warn!(
+                        "Skipping initial edit to protected code at byte {}",
+                        range.start
+                    );
+                    continue;
+                }
+                count += nodes.len();
+                for (i, node) in nodes.iter().enumerate() {
+                    edits = match (&edit.replacement, i) {
+                        (Some(replacement), 0) => edits.replace(node, replacement.clone()),
+                        _ => edits.omit(node),
+                    };
+                }
+            }
+        }
+        if count == 0 {
+            return Ok(edits);
+        }
+        let mut text = Vec::with_capacity(orig.text.len());
+        render(&mut text, &orig.tree, &orig.text, &edits)?;
+        if check.interesting(&text)? {
+            info!("Starting from initial edits, size: {}", text.len());
+            Ok(edits)
+        } else {
+            warn!("Initial edits aren't interesting, ignoring them");
+            Ok(Edits::new())
+        }
+    }
+}
+
+/// The outermost non-empty nodes that are inside of the range, in order
+fn outermost<'a>(orig: &'a Original, range: &Range<usize>) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.byte_range().is_empty() {
+            continue;
+        }
+        if range.start <= node.start_byte() && node.end_byte() <= range.end {
+            nodes.push(node);
+        } else if range.start < node.end_byte() && node.start_byte() < range.end {
+            let children: Vec<_> = node.children(&mut orig.tree.walk()).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::CmdCheck;
+
+    #[test]
+    fn test_edits() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "int x;\nint y;\nint z = 5;\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        let seed = Seed::parse(
+            r#"{"edits": [{"lines": [1, 2]}, {"query": "(number_literal)", "replacement": "0"}],
+                "hints": [{"bytes": [0, 3]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(seed.hints(&orig).unwrap(), vec![0..3]);
+        let check = CmdCheck::exit_zero("grep", &["-q", "z"]);
+        let edits = seed.edits(&orig, &check, &Protection::default()).unwrap();
+        let mut text = Vec::new();
+        render(&mut text, &orig.tree, &orig.text, &edits).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap().trim(), "int z = 0;");
+    }
+}
@@ -20,8 +20,20 @@ impl TaskId {
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub(super) enum Reduction {
     Delete(NodeId),
+    /// Delete a whole file (of several), by its root
+    DeleteFile(NodeId),
     DeleteAll(Vec<NodeId>),
-    Replace { node_id: NodeId, with: String },
+    ReplaceAll(Vec<(NodeId, String)>),
+    Replace {
+        node_id: NodeId,
+        with: String,
+    },
+    /// Replace a call with the function's body, and delete the definition
+    Inline {
+        call: NodeId,
+        definition: NodeId,
+        with: String,
+    },
     // Hoist(NodeId, NodeId),
     // Delta(NodeId),
 }
@@ -43,8 +55,11 @@ impl Task {
         match self {
             Task::Explore(_) => "explore".to_string(),
             Task::Reduce(Reduction::Delete(_)) => "delete".to_string(),
+            Task::Reduce(Reduction::DeleteFile(_)) => "delete_file".to_string(),
             Task::Reduce(Reduction::DeleteAll(_)) => "delete_all".to_string(),
             Task::Reduce(Reduction::Replace { .. }) => "replace".to_string(),
+            Task::Reduce(Reduction::ReplaceAll(_)) => "replace_all".to_string(),
+            Task::Reduce(Reduction::Inline { .. }) => "inline".to_string(),
         }
     }
 }
@@ -0,0 +1,184 @@
+//! Delete comments and normalize whitespace.
+//
+// The main passes only ever delete or replace whole nodes, so they leave behind
+// the whitespace that surrounded them, and they never try deleting comments
+// that the grammar attaches to a node that has to stay.
+
+use tracing::{debug, info};
+use tree_sitter::Node;
+use tree_sitter_edit::render;
+
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::original::Original;
+
+use super::error::ReductionError;
+
+/// Group testing: try applying all of the changes at once, then (if that
+/// wasn't interesting) each half, and so on. Returns the accepted changes.
+pub(super) fn accept<C, T: Check>(
+    changes: Vec<C>,
+    check: &T,
+    mut candidate: impl FnMut(&[C], &[C]) -> Result<Vec<u8>, ReductionError>,
+) -> Result<Vec<C>, ReductionError> {
+    let mut accepted = Vec::new();
+    let mut groups = vec![changes];
+    while let Some(mut group) = groups.pop() {
+        if group.is_empty() {
+            continue;
+        }
+        let text = candidate(&accepted, &group)?;
+        if check.interesting(&text)? {
+            debug!("Accepted {} changes", group.len());
+            accepted.extend(group);
+        } else if group.len() > 1 {
+            let half = group.split_off(group.len() / 2);
+            groups.push(half);
+            groups.push(group);
+        }
+    }
+    Ok(accepted)
+}
+
+fn extras(orig: &Original) -> Vec<NodeId> {
+    let mut ids = Vec::new();
+    let mut queue = vec![orig.tree.root_node()];
+    while let Some(node) = queue.pop() {
+        if node.is_extra() && !node.is_missing() && !node.byte_range().is_empty() {
+            ids.push(NodeId::new(&node));
+        } else {
+            queue.extend(node.children(&mut orig.tree.walk()));
+        }
+    }
+    ids
+}
+
+/// Delete comments (and any other `extra` nodes).
+pub(super) fn delete_comments<T: Check>(
+    orig: &Original,
+    check: &T,
+) -> Result<Edits, ReductionError> {
+    let ids = extras(orig);
+    info!("Trying to delete {} comments", ids.len());
+    let deleted = accept(ids, check, |accepted, group| {
+        let edits = Edits::new().omit_ids(accepted).omit_ids(group);
+        let mut text = Vec::with_capacity(orig.text.len());
+        render(&mut text, &orig.tree, &orig.text, &edits)?;
+        Ok(text)
+    })?;
+    Ok(Edits::new().omit_ids(&deleted))
+}
+
+/// Whitespace inside these is significant, so treat them as single tokens.
+fn is_atomic(node: &Node<'_>) -> bool {
+    let kind = node.kind();
+    node.child_count() == 0
+        || node.is_extra()
+        || kind.contains("string")
+        || kind.contains("char")
+        || kind.contains("comment")
+}
+
+/// The byte ranges of the tokens of the program, in order
+fn tokens(orig: &Original) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if is_atomic(&node) {
+            if !node.byte_range().is_empty() {
+                ranges.push(node.byte_range());
+            }
+        } else {
+            let children: Vec<_> = node.children(&mut orig.tree.walk()).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    ranges
+}
+
+/// A stretch of whitespace between tokens, and what to replace it with
+#[derive(Clone, Debug)]
+struct Gap {
+    start: usize,
+    end: usize,
+    with: &'static str,
+}
+
+fn gaps(orig: &Original) -> Vec<Gap> {
+    let tokens = tokens(orig);
+    let mut gaps = Vec::new();
+    let mut start = 0;
+    for (i, end) in tokens
+        .iter()
+        .map(|r| r.start)
+        .chain(std::iter::once(orig.text.len()))
+        .enumerate()
+    {
+        let Some(between) = orig.text.get(start..end) else {
+            continue;
+        };
+        if !between.is_empty() && between.iter().all(u8::is_ascii_whitespace) {
+            let with = if i == 0 {
+                ""
+            } else if between.contains(&b'\n') {
+                "\n"
+            } else {
+                " "
+            };
+            if between != with.as_bytes() {
+                gaps.push(Gap { start, end, with });
+            }
+        }
+        if let Some(token) = tokens.get(i) {
+            start = token.end;
+        }
+    }
+    gaps
+}
+
+fn apply(text: &[u8], gaps: impl IntoIterator<Item = Gap>) -> Vec<u8> {
+    let mut gaps: Vec<Gap> = gaps.into_iter().collect();
+    gaps.sort_by_key(|g| g.start);
+    let mut out = Vec::with_capacity(text.len());
+    let mut pos = 0;
+    for gap in gaps {
+        out.extend_from_slice(&text[pos..gap.start]);
+        out.extend_from_slice(gap.with.as_bytes());
+        pos = gap.end;
+    }
+    out.extend_from_slice(&text[pos..]);
+    out
+}
+
+/// Collapse whitespace between tokens into a single space or newline.
+pub(super) fn normalize_whitespace<T: Check>(
+    orig: &Original,
+    check: &T,
+) -> Result<Vec<u8>, ReductionError> {
+    let gaps = gaps(orig);
+    info!("Trying to normalize {} stretches of whitespace", gaps.len());
+    let accepted = accept(gaps, check, |accepted, group| {
+        Ok(apply(&orig.text, accepted.iter().chain(group).cloned()))
+    })?;
+    Ok(apply(&orig.text, accepted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaps() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_c::LANGUAGE.into())
+            .unwrap();
+        let src = "\n  int  main ( )\n\n\n  {\n    puts(\"a  b\");  }\n\n";
+        let orig = Original::new(parser.parse(src, None).unwrap(), src.as_bytes().to_vec());
+        assert_eq!(
+            String::from_utf8(apply(&orig.text, gaps(&orig))).unwrap(),
+            "int main ( )\n{\nputs(\"a  b\"); }\n"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! User-supplied rewrite rules, written as tree-sitter queries.
+//!
+//! A rules file is a sequence of rules of the form `QUERY => TEMPLATE`. The
+//! query is a single tree-sitter query pattern, and may span several lines. The
+//! template is the rest of the line after the first `=>` that isn't inside the
+//! pattern's parentheses or a string, and may refer to the query's captures
+//! like `@name`, which stand for the text of the captured nodes (`@@` stands
+//! for `@`). For example,
+//!
+//! ```text
+//! ; Replace calls with their only argument
+//! (call_expression
+//!   arguments: (argument_list (_) @a)) => @a
+//! (for_statement body: (_) @b) => @b
+//! ```
+//!
+//! Each node matched by the query (or captured as `@root`, if the query has
+//! such a capture) is a candidate for replacement by the template. Lines
+//! starting with `;` are comments.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+use tree_sitter::{Language, Query, QueryCursor, QueryError, StreamingIterator, Tree};
+
+use crate::id::NodeId;
+
+const SEPARATOR: &str = "=>";
+
+/// Name of the capture for the node to replace
+const ROOT: &str = "root";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rewrite {
+    pub query: String,
+    pub template: String,
+}
+
+#[derive(Debug, Error)]
+pub enum RewriteError {
+    #[error("line {0}: expected `{SEPARATOR}` after query")]
+    Syntax(usize),
+    #[error("rule {rule}: bad query")]
+    Query { rule: usize, source: QueryError },
+    #[error("rule {rule}: unknown capture @{name} in template")]
+    Capture { rule: usize, name: String },
+}
+
+impl Rewrite {
+    /// Parse the contents of a rules file.
+    ///
+    /// # Errors
+    ///
+    /// If there is a query that isn't followed by a template.
+    pub fn parse_rules(text: &str) -> Result<Vec<Rewrite>, RewriteError> {
+        let mut rules = Vec::new();
+        let mut query = String::new();
+        let mut query_line = 0;
+        // Of the query, in parentheses and brackets
+        let mut depth = 0;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim_start().starts_with(';') {
+                continue;
+            }
+            if query.trim().is_empty() {
+                query_line = i + 1;
+            }
+            if let Some(at) = separator(line, &mut depth) {
+                query.push_str(&line[..at]);
+                rules.push(Rewrite {
+                    query: query.trim().to_string(),
+                    template: line[at + SEPARATOR.len()..].trim().to_string(),
+                });
+                query.clear();
+            } else {
+                query.push_str(line);
+                query.push('\n');
+            }
+        }
+        if !query.trim().is_empty() {
+            return Err(RewriteError::Syntax(query_line));
+        }
+        Ok(rules)
+    }
+}
+
+/// Where the separator is in a line of a rule, if it's there. `depth` is how
+/// deeply nested the query is at the start of the line, and is updated.
+fn separator(line: &str, depth: &mut usize) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' => *depth += 1,
+            ')' | ']' => *depth = depth.saturating_sub(1),
+            // The rest of the line is a comment
+            ';' => return None,
+            _ if *depth == 0 && line[i..].starts_with(SEPARATOR) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+enum Piece {
+    Text(String),
+    Capture(u32),
+}
+
+#[derive(Debug)]
+pub(crate) struct Compiled {
+    query: Query,
+    root: u32,
+    template: Vec<Piece>,
+}
+
+fn is_capture_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+/// Compile rules for a specific language.
+///
+/// # Errors
+///
+/// If a query is invalid, or a template refers to an unknown capture.
+pub(crate) fn compile(
+    rules: &[Rewrite],
+    language: &Language,
+) -> Result<Vec<Compiled>, RewriteError> {
+    let mut compiled = Vec::with_capacity(rules.len());
+    for (rule, rewrite) in rules.iter().enumerate() {
+        let rule = rule + 1;
+        let root_capture = format!("@{ROOT}");
+        let source = if rewrite.query.contains(&root_capture) {
+            rewrite.query.clone()
+        } else {
+            format!("{} {root_capture}", rewrite.query)
+        };
+        let query =
+            Query::new(language, &source).map_err(|source| RewriteError::Query { rule, source })?;
+        let capture = |name: &str| {
+            query
+                .capture_index_for_name(name)
+                .ok_or_else(|| RewriteError::Capture {
+                    rule,
+                    name: name.to_string(),
+                })
+        };
+        let root = capture(ROOT)?;
+        let mut template = Vec::new();
+        let mut rest = rewrite.template.as_str();
+        let mut text = String::new();
+        while let Some(at) = rest.find('@') {
+            text.push_str(&rest[..at]);
+            if rest[at + 1..].starts_with('@') {
+                text.push('@');
+                rest = &rest[at + 2..];
+                continue;
+            }
+            template.push(Piece::Text(std::mem::take(&mut text)));
+            let name_len = rest[at + 1..]
+                .find(|c| !is_capture_char(c))
+                .unwrap_or(rest.len() - at - 1);
+            template.push(Piece::Capture(capture(&rest[at + 1..at + 1 + name_len])?));
+            rest = &rest[at + 1 + name_len..];
+        }
+        text.push_str(rest);
+        template.push(Piece::Text(text));
+        compiled.push(Compiled {
+            query,
+            root,
+            template,
+        });
+    }
+    Ok(compiled)
+}
+
+/// Apply the rules to the tree, producing candidate replacements for nodes.
+pub(crate) fn rewrites(
+    rules: &[Compiled],
+    tree: &Tree,
+    text: &[u8],
+) -> HashMap<NodeId, Vec<String>> {
+    let mut rewrites: HashMap<NodeId, Vec<String>> = HashMap::new();
+    let mut cursor = QueryCursor::new();
+    for rule in rules {
+        let mut matches = cursor.matches(&rule.query, tree.root_node(), text);
+        while let Some(m) = matches.next() {
+            let Some(root) = m.nodes_for_capture_index(rule.root).next() else {
+                continue;
+            };
+            let mut rewritten = Vec::new();
+            for piece in &rule.template {
+                match piece {
+                    Piece::Text(s) => rewritten.extend_from_slice(s.as_bytes()),
+                    Piece::Capture(i) => {
+                        let mut nodes = m.nodes_for_capture_index(*i);
+                        if let Some(first) = nodes.next() {
+                            let end = nodes.last().unwrap_or(first).end_byte();
+                            rewritten.extend_from_slice(&text[first.start_byte()..end]);
+                        }
+                    }
+                }
+            }
+            let Ok(rewritten) = String::from_utf8(rewritten) else {
+                continue;
+            };
+            let candidates = rewrites.entry(NodeId::new(&root)).or_default();
+            if !candidates.contains(&rewritten) {
+                candidates.push(rewritten);
+            }
+        }
+    }
+    rewrites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrites() {
+        let rules = Rewrite::parse_rules(
+            "; Replace calls with their only argument\n\
+             (call_expression\n  arguments: (argument_list (_) @a)) => @a\n\
+             \n\
+             (call_expression function: (_) @f) => @f()\n",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        let language: Language = tree_sitter_c::LANGUAGE.into();
+        let compiled = compile(&rules, &language).unwrap();
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&language).unwrap();
+        let src = "int main() { f(g(1)); }";
+        let tree = parser.parse(src, None).unwrap();
+        let mut all: Vec<String> = rewrites(&compiled, &tree, src.as_bytes())
+            .into_values()
+            .flatten()
+            .collect();
+        all.sort();
+        assert_eq!(all, vec!["1", "f()", "g()", "g(1)"]);
+
+        let bad = Rewrite::parse_rules("(call_expression) => @nope").unwrap();
+        assert!(matches!(
+            compile(&bad, &language),
+            Err(RewriteError::Capture { .. })
+        ));
+        assert!(matches!(
+            Rewrite::parse_rules("(call_expression)"),
+            Err(RewriteError::Syntax(1))
+        ));
+
+        let rules = Rewrite::parse_rules(
+            "(arrow_function \"=>\"\n  body: (_) @b) => () => @b\n\
+             ((identifier) @i (#eq? @i \"=>\")) => @@@i\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules[0],
+            Rewrite {
+                query: "(arrow_function \"=>\"\n  body: (_) @b)".to_string(),
+                template: "() => @b".to_string(),
+            }
+        );
+        assert_eq!(rules[1].query, "((identifier) @i (#eq? @i \"=>\"))");
+        let rules = Rewrite::parse_rules("(identifier) @i => @@@i").unwrap();
+        let compiled = compile(&rules, &language).unwrap();
+        let found: Vec<String> = rewrites(&compiled, &tree, src.as_bytes())
+            .into_values()
+            .flatten()
+            .collect();
+        assert!(found.contains(&"@main".to_string()));
+    }
+}
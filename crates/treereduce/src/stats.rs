@@ -2,20 +2,32 @@ use std::io;
 use std::io::Write;
 use std::time::Duration;
 
+use tree_sitter::Point;
+
+use crate::metric::Metric;
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub struct Pass {
     pub duration: Duration,
+    /// The unit of the sizes
+    pub metric: Metric,
     pub start_size: usize,
     pub end_size: usize,
 }
 
 impl Pass {
     pub fn write_text(&self, w: &mut impl Write) -> io::Result<()> {
-        debug_assert!(self.end_size <= self.start_size);
-
         writeln!(w, "Duration   : {}s", self.duration.as_secs())?;
-        writeln!(w, "Start size : {} bytes", self.start_size)?;
-        writeln!(w, "End size   : {} bytes", self.end_size)?;
+        writeln!(w, "Start size : {} {}", self.start_size, self.metric.unit())?;
+        writeln!(w, "End size   : {} {}", self.end_size, self.metric.unit())?;
         writeln!(
             w,
             "Reduction  : {:.2}%",
@@ -27,9 +39,11 @@ impl Pass {
         )?;
         writeln!(
             w,
-            "Bytes/sec  : {:.2}",
+            "{:<11}: {:.2}",
+            format!("{}/sec", capitalize(self.metric.unit())),
             if self.duration.as_millis() != 0 {
-                ((self.start_size - self.end_size) as f64 / self.duration.as_millis() as f64)
+                (self.start_size.saturating_sub(self.end_size) as f64
+                    / self.duration.as_millis() as f64)
                     * 1000.0
             } else {
                 0.0
@@ -39,12 +53,52 @@ impl Pass {
     }
 }
 
+/// A node that can't be deleted or replaced without making the test case
+/// uninteresting
+#[derive(Debug)]
+pub struct Essential {
+    /// Index of the file (of several) that the node is in
+    pub file: usize,
+    pub kind: String,
+    pub start: Point,
+    pub text: String,
+}
+
+impl Essential {
+    const MAX_TEXT: usize = 40;
+
+    /// Prefixed with the file name, if given
+    pub fn write_text(&self, w: &mut impl Write, file: Option<&str>) -> io::Result<()> {
+        if let Some(file) = file {
+            write!(w, "{file}:")?;
+        }
+        let trimmed = self.text.trim();
+        let line = trimmed.lines().next().unwrap_or_default();
+        let mut text: String = line.chars().take(Self::MAX_TEXT).collect();
+        if text.len() < trimmed.len() {
+            text.push_str("...");
+        }
+        writeln!(
+            w,
+            "{}:{} {} `{}`",
+            self.start.row + 1,
+            self.start.column + 1,
+            self.kind,
+            text
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct Stats {
     pub duration: Duration,
+    /// The unit of the sizes
+    pub metric: Metric,
     pub start_size: usize,
     pub end_size: usize,
     pub passes: Vec<Pass>,
+    /// The remaining nodes, if the result was verified to be 1-minimal
+    pub essential: Option<Vec<Essential>>,
 }
 
 impl Default for Stats {
@@ -57,15 +111,15 @@ impl Stats {
     pub fn new() -> Self {
         Stats {
             duration: Duration::new(0, 0),
+            metric: Metric::Bytes,
             start_size: 0,
             end_size: 0,
             passes: Vec::new(),
+            essential: None,
         }
     }
 
     pub fn write_text(&self, w: &mut impl Write) -> io::Result<()> {
-        debug_assert!(self.end_size <= self.start_size);
-
         for (i, pass) in self.passes.iter().enumerate() {
             writeln!(w)?;
             writeln!(w, "Pass {} / {}", i + 1, self.passes.len())?;
@@ -77,8 +131,8 @@ impl Stats {
         writeln!(w, "Total")?;
         writeln!(w, "-----")?;
         writeln!(w, "Duration   : {}s", self.duration.as_secs())?;
-        writeln!(w, "Start size : {} bytes", self.start_size)?;
-        writeln!(w, "End size   : {} bytes", self.end_size)?;
+        writeln!(w, "Start size : {} {}", self.start_size, self.metric.unit())?;
+        writeln!(w, "End size   : {} {}", self.end_size, self.metric.unit())?;
         writeln!(
             w,
             "Reduction  : {:.2}%",
@@ -86,4 +140,25 @@ impl Stats {
         )?;
         Ok(())
     }
+
+    /// The names of the files are only printed if there are several.
+    pub fn write_essential(&self, w: &mut impl Write, files: &[String]) -> io::Result<()> {
+        let Some(essential) = &self.essential else {
+            return Ok(());
+        };
+        writeln!(
+            w,
+            "Result is 1-minimal, each of its {} nodes is essential:",
+            essential.len()
+        )?;
+        for node in essential {
+            let file = if files.len() > 1 {
+                files.get(node.file).map(String::as_str)
+            } else {
+                None
+            };
+            node.write_text(w, file)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,258 @@
+//! Synthesize small replacement strings for each node kind.
+//
+// tree-sitter grammars don't ship their `grammar.json` with the Rust bindings,
+// so instead of deriving the shortest sentence for each rule from the grammar,
+// this module searches for one. Starting from the empty program, it repeatedly
+// extends short programs with each token that the parse table says may come
+// next, parses the result, and remembers the shortest text that parsed
+// (without errors) as each kind of node.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use tracing::debug;
+use tree_sitter::{Language, Node, Parser};
+
+use crate::node_types::NodeTypes;
+
+/// Stand-ins for named tokens like identifiers and literals, which don't have
+/// fixed text.
+const ATOMS: &[&str] = &["0", "a", "T", "int", "\"\"", "''", "0.0", "true", "nil"];
+
+/// Don't bother with programs longer than this
+const MAX_LEN: usize = 24;
+
+/// Upper bound on the number of programs to parse
+const MAX_PARSES: usize = 20_000;
+
+/// How many programs to extend that end in the same parse state
+const MAX_PER_STATE: usize = 1;
+
+/// The parser's initial state
+const START_STATE: u16 = 1;
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.len() <= MAX_LEN && !s.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Keep a space between these, e.g., `int a` or `#ifdef a #endif`
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '#'
+}
+
+fn last_leaf(node: Node<'_>) -> Option<Node<'_>> {
+    if node.child_count() == 0 {
+        return if node.is_missing() || node.is_extra() {
+            None
+        } else {
+            Some(node)
+        };
+    }
+    (0..node.child_count())
+        .rev()
+        .find_map(|i| node.child(i as u32).and_then(last_leaf))
+}
+
+/// Append a token to a program, with a space only where it's needed to keep
+/// the tokens separate.
+fn join(program: &mut String, token: &str) {
+    if program.ends_with(is_word_char) && token.starts_with(is_word_char) {
+        program.push(' ');
+    }
+    program.push_str(token);
+}
+
+struct Search<'a> {
+    language: &'a Language,
+    parser: Parser,
+    best: HashMap<String, String>,
+    /// Programs to parse, shortest first (ties broken by order of insertion)
+    queue: BinaryHeap<Reverse<(usize, usize, String)>>,
+    pushed: usize,
+    parses: usize,
+    per_state: HashMap<u16, usize>,
+}
+
+impl Search<'_> {
+    fn enqueue(&mut self, program: String) {
+        if program.len() <= MAX_LEN {
+            self.queue
+                .push(Reverse((program.len(), self.pushed, program)));
+            self.pushed += 1;
+        }
+    }
+
+    /// Record the text of every error-free named node in the tree.
+    fn harvest(&mut self, node: Node<'_>, text: &str) {
+        if !node.has_error() && node.is_named() && !node.is_extra() {
+            let s = &text[node.byte_range()];
+            let shorter = match self.best.get(node.kind()) {
+                Some(old) => s.len() < old.len(),
+                None => true,
+            };
+            if !s.trim().is_empty() && shorter {
+                self.best.insert(node.kind().to_string(), s.to_string());
+            }
+        }
+        for child in node.children(&mut node.walk()) {
+            self.harvest(child, text);
+        }
+    }
+
+    /// Text to append to a program to produce the given symbol
+    fn text_for(&self, symbol: u16) -> Option<String> {
+        if !self.language.node_kind_is_visible(symbol) {
+            return None;
+        }
+        let kind = self.language.node_kind_for_id(symbol)?;
+        if self.language.node_kind_is_named(symbol) {
+            self.best.get(kind).cloned()
+        } else if is_token(kind) {
+            Some(kind.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Fill in tokens that the parser reported as missing.
+    fn repair(&self, node: Node<'_>, text: &str, out: &mut Vec<String>) {
+        if node.is_missing() {
+            let kind = node.kind();
+            if let Some(s) = self.best.get(kind) {
+                out.push(s.clone());
+            } else if !node.is_named() && is_token(kind) {
+                out.push(kind.to_string());
+            }
+        } else if node.child_count() == 0 {
+            out.push(text[node.byte_range()].to_string());
+        } else {
+            for child in node.children(&mut node.walk()) {
+                self.repair(child, text, out);
+            }
+        }
+    }
+
+    /// Extend the program with each symbol that could come next.
+    fn extend(&mut self, program: &str, state: u16) {
+        let count = self.per_state.entry(state).or_default();
+        if *count >= MAX_PER_STATE {
+            return;
+        }
+        *count += 1;
+        let Some(lookahead) = self.language.lookahead_iterator(state) else {
+            return;
+        };
+        let symbols: Vec<u16> = lookahead.collect();
+        for symbol in symbols {
+            if let Some(text) = self.text_for(symbol) {
+                let mut extended = program.to_string();
+                join(&mut extended, &text);
+                self.enqueue(extended);
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        while let Some(Reverse((_, _, program))) = self.queue.pop() {
+            if self.parses >= MAX_PARSES {
+                break;
+            }
+            self.parses += 1;
+            let Some(tree) = self.parser.parse(&program, None) else {
+                continue;
+            };
+            let root = tree.root_node();
+            self.harvest(root, &program);
+            if let Some(leaf) = last_leaf(root) {
+                let state = leaf.next_parse_state();
+                // State 0 is the error state
+                if state != 0 {
+                    self.extend(&program, state);
+                }
+            }
+            if root.has_error() && !root.is_error() {
+                let mut pieces = Vec::new();
+                self.repair(root, &program, &mut pieces);
+                let mut repaired = String::new();
+                for piece in &pieces {
+                    join(&mut repaired, piece);
+                }
+                if repaired != program {
+                    self.enqueue(repaired);
+                }
+            }
+        }
+        self.queue.clear();
+    }
+}
+
+/// For each node kind (and supertype), find a short string that parses as that
+/// kind of node.
+///
+/// Kinds for which no such string was found are absent from the result.
+#[must_use]
+pub fn minimal_replacements(
+    language: &Language,
+    node_types: &NodeTypes,
+) -> HashMap<String, String> {
+    let mut parser = Parser::new();
+    if parser.set_language(language).is_err() {
+        return HashMap::new();
+    }
+    let mut search = Search {
+        language,
+        parser,
+        best: HashMap::new(),
+        queue: BinaryHeap::new(),
+        pushed: 0,
+        parses: 0,
+        per_state: HashMap::new(),
+    };
+    // Find out what kinds of named tokens the atoms are...
+    for atom in ATOMS {
+        search.enqueue((*atom).to_string());
+    }
+    search.run();
+    search.per_state.clear();
+    // ...then use them to build up larger programs.
+    search.extend("", START_STATE);
+    search.run();
+
+    let mut best = search.best;
+    for (supertype, subtypes) in node_types.all_subtypes() {
+        let shortest = subtypes
+            .iter()
+            .chain(std::iter::once(supertype))
+            .filter_map(|kind| best.get(kind))
+            .min_by_key(|s| (s.len(), s.as_str()));
+        if let Some(s) = shortest {
+            best.insert(supertype.clone(), s.clone());
+        }
+    }
+    debug!(
+        "Synthesized replacements for {} node kinds in {} parses",
+        best.len(),
+        search.parses
+    );
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minimal_replacements() {
+        let language: Language = tree_sitter_c::LANGUAGE.into();
+        let nt = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        let min = minimal_replacements(&language, &nt);
+        assert_eq!(min.get("number_literal").map(String::as_str), Some("0"));
+        assert_eq!(min.get("identifier").map(String::as_str), Some("a"));
+        assert_eq!(min.get("expression").map(String::len), Some(1));
+        assert_eq!(
+            min.get("compound_statement").map(String::as_str),
+            Some("{}")
+        );
+        assert_eq!(min.get("if_statement").map(String::as_str), Some("if(a);"));
+    }
+}